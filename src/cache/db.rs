@@ -1,8 +1,11 @@
-use super::{CachedKey, CachedValue, Result};
-use redb::{Database, ReadableTableMetadata, TableDefinition, TableHandle};
+use super::{flaky::FlakyHistory, CachedKey, CachedValue, Result};
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition, TableHandle};
 
 const TABLE: TableDefinition<CachedKey, CachedValue> = TableDefinition::new("plugin-cargo");
 
+/// `user/repo` => flakiness of tests across runs
+const FLAKY: TableDefinition<&str, FlakyHistory> = TableDefinition::new("plugin-cargo-flaky");
+
 fn db_file() -> String {
     const TAG_CACHE: &str = "TAG_CACHE";
     std::env::var(TAG_CACHE)
@@ -21,6 +24,7 @@ impl Db {
         {
             let write_txn = db.begin_write()?;
             write_txn.open_table(TABLE)?;
+            write_txn.open_table(FLAKY)?;
             write_txn.commit()?;
         }
         {
@@ -59,6 +63,25 @@ impl Db {
         info!("cache written");
         Ok(())
     }
+
+    /// Record flaky tests from a newly generated output, and attach the
+    /// history to the output.
+    pub fn update_flaky_history(&self, key: &CachedKey, val: &mut CachedValue) -> Result<()> {
+        let user_repo = format!("{}/{}", key.user, key.repo);
+        let write_txn = self.db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(FLAKY)?;
+            let mut history = table
+                .get(&*user_repo)?
+                .map(|val| val.value())
+                .unwrap_or_default();
+            history.record(key, val);
+            history.attach(val);
+            table.insert(&*user_repo, &history)?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
 }
 
 #[test]
@@ -101,8 +124,6 @@ fn test_db_list_table() -> Result<()> {
             .collect::<Vec<_>>(),
     );
 
-    use redb::ReadableTable;
-
     let repo_with_err = table.iter()?.find_map(|t| {
         let (k, v) = t.ok()?;
         let (k, v) = (k.value(), v.value());
@@ -0,0 +1,130 @@
+use super::{CachedKey, CachedValue};
+use plugin::prelude::{serde_json::Value, *};
+
+/// Flakiness of tests in a repo across runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FlakyHistory {
+    /// `Map<PkgName, Map<"test_binary$testcase", FlakyStat>>`
+    pkgs: IndexMap<String, IndexMap<String, FlakyStat>>,
+    /// the commit sha of the last recorded run; a regenerated output of the
+    /// same commit is not counted again
+    #[serde(default)]
+    last_sha: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct FlakyStat {
+    /// how many runs the test is in
+    runs: usize,
+    /// how many runs the test is flaky in
+    flaky_runs: usize,
+    /// the commit sha of the last flaky run
+    last_flaky_sha: Option<String>,
+}
+
+impl FlakyHistory {
+    /// Count testcases in the newly generated output.
+    pub fn record(&mut self, key: &CachedKey, val: &CachedValue) {
+        if self.last_sha.as_ref() == Some(&key.api.sha) {
+            return;
+        }
+        let Some(pkgs) = val.pkgs() else { return };
+        self.last_sha = Some(key.api.sha.clone());
+        for (pkg, output) in pkgs {
+            let history = self.pkgs.entry(pkg.clone()).or_default();
+            for (bin, testcase, flaky) in testcases(output) {
+                let stat = history.entry(format!("{bin}${testcase}")).or_default();
+                stat.runs += 1;
+                if flaky {
+                    stat.flaky_runs += 1;
+                    stat.last_flaky_sha = Some(key.api.sha.clone());
+                }
+            }
+        }
+    }
+
+    /// Attach `flaky_history` to each package in the output: tests that have
+    /// ever been flaky, the most frequently flaky first.
+    pub fn attach(&self, val: &mut CachedValue) {
+        let Some(pkgs) = val.pkgs_mut() else { return };
+        for (pkg, output) in pkgs {
+            let Some(output) = output.as_object_mut() else {
+                continue;
+            };
+            let mut list: Vec<_> = self
+                .pkgs
+                .get(pkg)
+                .into_iter()
+                .flatten()
+                .filter(|(_, stat)| stat.flaky_runs != 0)
+                .collect();
+            list.sort_by(|a, b| b.1.flaky_runs.cmp(&a.1.flaky_runs).then(a.0.cmp(b.0)));
+            let list: Vec<_> = list
+                .into_iter()
+                .map(|(test, stat)| serde_json::json!({ "test": test, "stat": stat }))
+                .collect();
+            output.insert("flaky_history".to_owned(), list.into());
+        }
+    }
+}
+
+/// Iterate over `(binary_name, testcase_name, flaky)` in a package output.
+fn testcases(output: &Value) -> impl Iterator<Item = (&str, &str, bool)> {
+    let bins = output["testcases"]["tests"]
+        .as_array()
+        .into_iter()
+        .flatten();
+    bins.flat_map(|bin| {
+        let bin_name = bin["binary_name"].as_str().unwrap_or_default();
+        let cases = bin["testcases"].as_array().into_iter().flatten();
+        cases.filter_map(move |case| {
+            let name = case["name"].as_str()?;
+            Some((bin_name, name, case["flaky"].as_bool().unwrap_or(false)))
+        })
+    })
+}
+
+#[test]
+fn record_and_attach() {
+    let key = CachedKey {
+        user: "user".to_owned(),
+        repo: "repo".to_owned(),
+        api: super::Api {
+            branch: "main".to_owned(),
+            sha: "abc".to_owned(),
+        },
+    };
+    let output = |flaky: bool| {
+        CachedValue::new(serde_json::json!({
+            "pkgs": { "pkg": { "testcases": { "tests": [{
+                "binary_name": "bin",
+                "testcases": [
+                    { "name": "flaky", "flaky": flaky },
+                    { "name": "stable", "flaky": false }
+                ]
+            }]}}}
+        }))
+    };
+
+    let mut history = FlakyHistory::default();
+    history.record(&key, &output(true));
+    // a forced regeneration of the same commit
+    history.record(&key, &output(true));
+    let key = CachedKey {
+        api: super::Api {
+            sha: "def".to_owned(),
+            ..key.api
+        },
+        ..key
+    };
+    history.record(&key, &output(false));
+
+    let mut val = output(false);
+    history.attach(&mut val);
+    let json = val.into_json();
+    let list = json["pkgs"]["pkg"]["flaky_history"].as_array().unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0]["test"], "bin$flaky");
+    assert_eq!(list[0]["stat"]["runs"], 2);
+    assert_eq!(list[0]["stat"]["flaky_runs"], 1);
+}
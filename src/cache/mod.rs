@@ -6,6 +6,7 @@ mod types;
 pub use types::{Api, CachedKey, CachedValue};
 
 mod db;
+mod flaky;
 mod gh;
 
 /// Output json when error happens.
@@ -54,6 +55,20 @@ fn gen_cache_consuming_error(user_repo: &str, key: CachedKey) -> (CachedKey, Cac
     }
 }
 
+/// Generate a new cache and track flaky tests in it across runs.
+/// Failing to track flaky tests doesn't discard the generated cache.
+fn gen_cache_tracking_flaky(
+    db: &db::Db,
+    user_repo: &str,
+    key: CachedKey,
+) -> (CachedKey, CachedValue) {
+    let (key, mut val) = gen_cache_consuming_error(user_repo, key);
+    if let Err(err) = db.update_flaky_history(&key, &mut val) {
+        error!(?err, "Failed to update the flaky history");
+    }
+    (key, val)
+}
+
 /// Get a local cache if any, otherwise download the repo and generate the cache.
 pub fn get_or_gen_cache(user_repo: &str) -> Result<(CachedKey, CachedValue)> {
     let key = gh::graphql_api(user_repo)?;
@@ -62,11 +77,11 @@ pub fn get_or_gen_cache(user_repo: &str) -> Result<(CachedKey, CachedValue)> {
     let db = db::Db::open()?;
     let force = std::env::var("OS_CHECKER_FORCE_PLUGIN_CARGO");
     let (key, mut val) = if let Ok("true") = force.as_deref() {
-        gen_cache_consuming_error(user_repo, key)
+        gen_cache_tracking_flaky(&db, user_repo, key)
     } else {
         match db.load_cache(&key)? {
             Some(val) => (key, val),
-            None => gen_cache_consuming_error(user_repo, key),
+            None => gen_cache_tracking_flaky(&db, user_repo, key),
         }
     };
     val.update_timestamp();
@@ -1,3 +1,4 @@
+use super::flaky::FlakyHistory;
 use os_checker_plugin_cargo::repo::GitInfo;
 use plugin::prelude::serde_json;
 use serde::{Deserialize, Serialize};
@@ -63,6 +64,15 @@ impl CachedValue {
         self.inner
     }
 
+    /// Package outputs; None if the output is an error.
+    pub fn pkgs(&self) -> Option<&serde_json::Map<String, serde_json::Value>> {
+        self.inner.get("pkgs")?.as_object()
+    }
+
+    pub fn pkgs_mut(&mut self) -> Option<&mut serde_json::Map<String, serde_json::Value>> {
+        self.inner.get_mut("pkgs")?.as_object_mut()
+    }
+
     // update end timestamp
     pub fn update_timestamp(&mut self) {
         const TIMESTAMP: &str = "timestamp";
@@ -125,8 +135,8 @@ impl redb::Value for CachedValue {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Api {
-    branch: String,
-    sha: String,
+    pub branch: String,
+    pub sha: String,
 }
 
 impl From<GitInfo> for Api {
@@ -137,3 +147,37 @@ impl From<GitInfo> for Api {
         }
     }
 }
+
+impl redb::Value for FlakyHistory {
+    type SelfType<'a>
+        = Self
+    where
+        Self: 'a;
+
+    type AsBytes<'a>
+        = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        serde_json::from_slice(data).expect("Failed to deserialize FlakyHistory from bytes.")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b,
+    {
+        serde_json::to_vec(value).expect("Failed to serialize FlakyHistory into bytes.")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("[plugin-cargo] FlakyHistory")
+    }
+}
@@ -0,0 +1,160 @@
+//! Options specified by environment variables.
+use crate::repo::Sanitizer;
+use std::str::FromStr;
+
+/// Read an environment variable and parse it, falling back to `default`
+/// if it's absent or invalid.
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(val) => val.trim().parse().unwrap_or_else(|_| {
+            error!(
+                name,
+                val, "Invalid value in environment variable; use the default."
+            );
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Read a comma-separated list from an environment variable.
+fn env_list(name: &str) -> Vec<String> {
+    let list = std::env::var(name).unwrap_or_default();
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// How many times a failing test is retried by nextest. No retry by default
+/// since a test passing on a retry is no longer reported as failed.
+///
+/// A test that fails first and passes on a retry is flaky.
+pub fn nextest_retries() -> u32 {
+    env_or("OS_CHECKER_PLUGIN_CARGO_RETRIES", 0)
+}
+
+/// Max size in bytes of stdout and stderr tails kept in a test failure.
+/// The full output is written to side files.
+pub fn output_limit() -> usize {
+    env_or("OS_CHECKER_PLUGIN_CARGO_OUTPUT_LIMIT", 4096)
+}
+
+/// Whether to collect code coverage of tests. Off by default since it
+/// builds and runs tests again.
+pub fn coverage() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_COVERAGE", false)
+}
+
+/// Sanitizers to run tests with, separated by commas, e.g. `address,leak`.
+/// None by default.
+pub fn sanitizers() -> Vec<Sanitizer> {
+    env_list("OS_CHECKER_PLUGIN_CARGO_SANITIZERS")
+        .iter()
+        .filter_map(|s| {
+            s.parse()
+                .inspect_err(|err| error!(?err, "Invalid sanitizer"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Whether to rerun a test with `-Zmiri-disable-isolation` if it's blocked
+/// by Miri's isolation.
+pub fn miri_disable_isolation_retry() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_MIRI_DISABLE_ISOLATION", false)
+}
+
+/// Whether to count unsafe code in dependencies, like cargo-geiger.
+pub fn unsafe_deps() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_UNSAFE_DEPS", false)
+}
+
+/// Path to a local checkout of the RustSec advisory database. Advisories
+/// are not scanned if it's absent.
+pub fn advisory_db() -> Option<String> {
+    std::env::var("OS_CHECKER_PLUGIN_CARGO_ADVISORY_DB")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+}
+
+/// SPDX ids of licenses allowed in dependencies, separated by commas, e.g.
+/// `MIT,Apache-2.0`. Every dependency is allowed if it's empty.
+pub fn license_allow() -> Vec<String> {
+    env_list("OS_CHECKER_PLUGIN_CARGO_LICENSE_ALLOW")
+}
+
+/// SPDX ids of licenses denied in dependencies, separated by commas.
+pub fn license_deny() -> Vec<String> {
+    env_list("OS_CHECKER_PLUGIN_CARGO_LICENSE_DENY")
+}
+
+/// Locally installed toolchains to verify MSRV with, separated by commas,
+/// e.g. `1.70,1.75,stable`. MSRV is not checked if it's empty.
+pub fn msrv_toolchains() -> Vec<String> {
+    env_list("OS_CHECKER_PLUGIN_CARGO_MSRV_TOOLCHAINS")
+}
+
+/// Whether to build docs of each package like docs.rs. Off by default
+/// since it runs `cargo doc` and `cargo rustdoc` for each package.
+pub fn docs() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_DOCS", false)
+}
+
+/// Whether to compare the public API with the last release on crates.io.
+/// Off by default since it builds rustdoc JSON twice.
+pub fn semver() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_SEMVER", false)
+}
+
+/// Whether to check the feature powerset of each package like cargo-hack.
+pub fn feature_powerset() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_FEATURE_POWERSET", false)
+}
+
+/// Max number of features combined in the powerset.
+pub fn feature_depth() -> usize {
+    env_or("OS_CHECKER_PLUGIN_CARGO_FEATURE_DEPTH", 2)
+}
+
+/// Features excluded from the powerset, separated by commas.
+pub fn feature_skip() -> Vec<String> {
+    env_list("OS_CHECKER_PLUGIN_CARGO_FEATURE_SKIP")
+}
+
+/// Whether to check unused dependencies with the `unused_crate_dependencies`
+/// lint. It's off by default since it checks all targets of each package
+/// once more.
+pub fn unused_deps() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_UNUSED_DEPS", false)
+}
+
+/// The crates.io index to read: a URL of the sparse index, or a path of a
+/// local index mirror to work offline.
+pub fn index_source() -> String {
+    env_or(
+        "OS_CHECKER_PLUGIN_CARGO_INDEX",
+        "https://index.crates.io".to_owned(),
+    )
+}
+
+/// Whether to report outdated direct dependencies. It's off by default
+/// since the index file of each dependency is fetched.
+pub fn outdated() -> bool {
+    env_or("OS_CHECKER_PLUGIN_CARGO_OUTDATED", false)
+}
+
+/// Path to a crates.io `db-dump.tar.gz` for downloads, reverse dependencies
+/// and owners. They are not reported if it's absent.
+pub fn db_dump() -> Option<String> {
+    std::env::var("OS_CHECKER_PLUGIN_CARGO_DB_DUMP")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+}
+
+/// Max total size in bytes of downloaded tarballs kept for reuse. The least
+/// recently used ones are removed beyond it.
+pub fn tarball_store_limit() -> u64 {
+    env_or("OS_CHECKER_PLUGIN_CARGO_TARBALL_STORE_LIMIT", 1 << 30)
+}
@@ -0,0 +1,326 @@
+use crate::repo::local_base_dir;
+use cargo_metadata::semver::Version;
+use plugin::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+    sync::LazyLock,
+};
+
+/// Popularity of a crate from the crates.io database dump.
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+pub struct CrateStats {
+    pub downloads: u64,
+    /// downloads in the last 90 days
+    pub recent_downloads: u64,
+    /// crates whose latest version depends on this crate
+    pub reverse_dependencies: usize,
+    /// GitHub logins of users and teams
+    pub owners: Vec<String>,
+}
+
+static DB_DUMP: LazyLock<Option<IndexMap<String, CrateStats>>> = LazyLock::new(|| {
+    let path = crate::config::db_dump()?;
+    load(Utf8Path::new(&path))
+        .inspect_err(|err| error!(?err, path, "Failed to load the db-dump"))
+        .ok()
+});
+
+/// Stats of a crate, if the db-dump is configured and has the crate.
+pub fn crate_stats(name: &str) -> Option<CrateStats> {
+    DB_DUMP.as_ref()?.get(name).cloned()
+}
+
+const FILES: &[&str] = &[
+    "crates.csv",
+    "versions.csv",
+    "version_downloads.csv",
+    "dependencies.csv",
+    "crate_owners.csv",
+    "users.csv",
+    "teams.csv",
+];
+
+/// Extract the needed csv files from `db-dump.tar.gz`, unless they are
+/// extracted after the dump is modified.
+fn load(dump: &Utf8Path) -> Result<IndexMap<String, CrateStats>> {
+    let dir = local_base_dir().join("db-dump");
+    let modified = |path: &Utf8Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let fresh = find_data_dir(&dir)
+        .is_some_and(|data| modified(&data.join("crates.csv")) >= modified(dump));
+    if !fresh {
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+        std::fs::create_dir_all(&dir)?;
+        info!(%dump, "Extract the db-dump");
+        let mut args = vec![
+            "xzf".to_owned(),
+            dump.to_string(),
+            "-C".to_owned(),
+            dir.to_string(),
+        ];
+        // extracted files get the current mtime
+        args.extend(["--touch".to_owned(), "--wildcards".to_owned()]);
+        args.extend(FILES.iter().map(|f| format!("*/data/{f}")));
+        duct::cmd("tar", args).stdout_null().stderr_null().run()?;
+    }
+    let data = find_data_dir(&dir).with_context(|| format!("No data dir in {dump}"))?;
+    load_data_dir(&data)
+}
+
+/// The dump has a single top dir named by date, e.g. `2025-01-01-020011/data`.
+fn find_data_dir(dir: &Utf8Path) -> Option<Utf8PathBuf> {
+    dir.read_dir_utf8()
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path().join("data"))
+        .find(|data| data.join("crates.csv").exists())
+}
+
+fn load_data_dir(data: &Utf8Path) -> Result<IndexMap<String, CrateStats>> {
+    // keyed by crate id until the end
+    let mut names = HashMap::<u64, String>::new();
+    let mut stats = HashMap::<u64, CrateStats>::new();
+    read_csv(
+        &data.join("crates.csv"),
+        &["id", "name", "downloads"],
+        |row| {
+            let id = row[0].parse()?;
+            names.insert(id, row[1].to_owned());
+            stats.insert(
+                id,
+                CrateStats {
+                    downloads: row[2].parse()?,
+                    ..Default::default()
+                },
+            );
+            Ok(())
+        },
+    )?;
+
+    // version id -> crate id, and the latest version of each crate: the
+    // highest unyanked one by semver, preferring non-prereleases, since a
+    // patch of an old line may be published after a newer release
+    let mut versions = HashMap::<u64, u64>::new();
+    let mut latest = HashMap::<u64, ((bool, Version), u64)>::new();
+    read_csv(
+        &data.join("versions.csv"),
+        &["id", "crate_id", "num", "yanked"],
+        |row| {
+            let (id, crate_id) = (row[0].parse()?, row[1].parse()?);
+            versions.insert(id, crate_id);
+            if row[3] == "t" {
+                return Ok(());
+            }
+            let Ok(version) = Version::parse(row[2]) else {
+                return Ok(());
+            };
+            let key = (version.pre.is_empty(), version);
+            if latest.get(&crate_id).is_none_or(|(max, _)| *max < key) {
+                latest.insert(crate_id, (key, id));
+            }
+            Ok(())
+        },
+    )?;
+    let latest: HashSet<u64> = latest.into_values().map(|(_, id)| id).collect();
+
+    // the dump only keeps downloads of the last 90 days
+    read_csv(
+        &data.join("version_downloads.csv"),
+        &["version_id", "downloads"],
+        |row| {
+            let version_id: u64 = row[0].parse()?;
+            if let Some(s) = versions.get(&version_id).and_then(|id| stats.get_mut(id)) {
+                s.recent_downloads += row[1].parse::<u64>()?;
+            }
+            Ok(())
+        },
+    )?;
+
+    let mut edges = HashSet::<(u64, u64)>::new();
+    read_csv(
+        &data.join("dependencies.csv"),
+        &["version_id", "crate_id"],
+        |row| {
+            let version_id: u64 = row[0].parse()?;
+            if let (true, Some(from)) = (latest.contains(&version_id), versions.get(&version_id)) {
+                edges.insert((*from, row[1].parse()?));
+            }
+            Ok(())
+        },
+    )?;
+    for (_, to) in edges {
+        if let Some(s) = stats.get_mut(&to) {
+            s.reverse_dependencies += 1;
+        }
+    }
+
+    let mut users = HashMap::<u64, String>::new();
+    read_csv(&data.join("users.csv"), &["id", "gh_login"], |row| {
+        users.insert(row[0].parse()?, row[1].to_owned());
+        Ok(())
+    })?;
+    let mut teams = HashMap::<u64, String>::new();
+    read_csv(&data.join("teams.csv"), &["id", "login"], |row| {
+        teams.insert(row[0].parse()?, row[1].to_owned());
+        Ok(())
+    })?;
+    read_csv(
+        &data.join("crate_owners.csv"),
+        &["crate_id", "owner_id", "owner_kind"],
+        |row| {
+            let owner_id = row[1].parse()?;
+            // 0 for users and 1 for teams
+            let owner = if row[2] == "1" {
+                teams.get(&owner_id)
+            } else {
+                users.get(&owner_id)
+            };
+            if let (Some(owner), Some(s)) = (owner, stats.get_mut(&row[0].parse()?)) {
+                s.owners.push(owner.clone());
+            }
+            Ok(())
+        },
+    )?;
+
+    info!(crates = stats.len(), "Loaded the db-dump");
+    Ok(stats
+        .into_iter()
+        .filter_map(|(id, s)| Some((names.remove(&id)?, s)))
+        .collect())
+}
+
+/// Read a csv file with a header, and pass the given columns of each row
+/// in the given order.
+fn read_csv(
+    path: &Utf8Path,
+    columns: &[&str],
+    mut f: impl FnMut(&[&str]) -> Result<()>,
+) -> Result<()> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {path}"))?;
+    let mut records = CsvRecords {
+        reader: std::io::BufReader::new(file),
+        line: String::new(),
+    };
+    let header = records
+        .next_record()?
+        .with_context(|| format!("{path} is empty"))?;
+    let idx = columns
+        .iter()
+        .map(|c| {
+            header
+                .iter()
+                .position(|h| h == c)
+                .with_context(|| format!("No column {c} in {path}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    while let Some(record) = records.next_record()? {
+        let row: Vec<&str> = idx
+            .iter()
+            .map(|i| record.get(*i).map_or("", |s| s.as_str()))
+            .collect();
+        f(&row).with_context(|| format!("Invalid row in {path}: {row:?}"))?;
+    }
+    Ok(())
+}
+
+/// A minimal csv reader: fields may be quoted with `"`, and quoted fields
+/// may contain commas, newlines and `""`.
+struct CsvRecords<R> {
+    reader: R,
+    line: String,
+}
+
+impl<R: BufRead> CsvRecords<R> {
+    fn next_record(&mut self) -> Result<Option<Vec<String>>> {
+        self.line.clear();
+        loop {
+            if self.reader.read_line(&mut self.line)? == 0 {
+                if self.line.is_empty() {
+                    return Ok(None);
+                }
+                break;
+            }
+            // an odd number of quotes means a newline in a quoted field
+            if self.line.matches('"').count().is_multiple_of(2) {
+                break;
+            }
+        }
+
+        let text = self.line.trim_end_matches(['\n', '\r']);
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+        fields.push(field);
+        Ok(Some(fields))
+    }
+}
+
+#[test]
+fn load_db_dump_csv() -> Result<()> {
+    let data = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+        .unwrap()
+        .join(format!(
+            "os-checker-plugin-cargo-db-dump-test-{}-{}",
+            std::process::id(),
+            Timestamp::now().as_nanosecond()
+        ));
+    std::fs::create_dir_all(&data)?;
+    let files = [
+        (
+            "crates.csv",
+            "created_at,description,downloads,id,name\n\
+             2020,\"a crate, with \"\"quotes\"\"\nand lines\",100,1,foo\n\
+             2021,b,20,2,bar\n",
+        ),
+        (
+            "versions.csv",
+            "crate_id,id,num,yanked\n1,10,0.1.0,f\n2,20,0.1.0,f\n2,21,0.2.0,f\n\
+             2,22,0.3.0,t\n2,23,0.1.1,f\n2,24,0.4.0-alpha.1,f\n",
+        ),
+        (
+            "version_downloads.csv",
+            "date,downloads,version_id\n2025,5,10\n2025,7,10\n2025,1,21\n",
+        ),
+        // bar 0.2.0 is the latest, not the yanked 0.3.0, the backported
+        // 0.1.1 or the prerelease; it depends on foo twice
+        (
+            "dependencies.csv",
+            "crate_id,id,kind,version_id\n1,1,0,20\n1,2,0,21\n1,3,2,21\n",
+        ),
+        (
+            "crate_owners.csv",
+            "crate_id,owner_id,owner_kind\n1,1,0\n1,1,1\n",
+        ),
+        ("users.csv", "gh_login,id\nalice,1\n"),
+        ("teams.csv", "id,login\n1,github:org:team\n"),
+    ];
+    for (name, text) in files {
+        std::fs::write(data.join(name), text)?;
+    }
+
+    let stats = load_data_dir(&data)?;
+    let foo = &stats["foo"];
+    assert_eq!(foo.downloads, 100);
+    assert_eq!(foo.recent_downloads, 12);
+    assert_eq!(foo.reverse_dependencies, 1);
+    assert_eq!(foo.owners, ["alice", "github:org:team"]);
+    assert_eq!(stats["bar"].recent_downloads, 1);
+    assert_eq!(stats["bar"].reverse_dependencies, 0);
+
+    std::fs::remove_dir_all(&data)?;
+    Ok(())
+}
@@ -1,4 +1,11 @@
 mod release_count;
-pub use release_count::IndexFile;
+pub use release_count::{index_path, Data, IndexFile};
+
+mod registry;
+pub use registry::Registry;
 
 mod release_tarball;
+mod tarball_store;
+
+mod db_dump;
+pub use db_dump::{crate_stats, CrateStats};
@@ -0,0 +1,290 @@
+use super::index_path;
+use crate::repo::{cargo_home, local_base_dir};
+use cargo_metadata::{semver::Version, Package};
+use plugin::prelude::*;
+use std::sync::{LazyLock, Mutex};
+
+/// Where to read the index file of a package: a URL of the sparse index
+/// or a path in a local index.
+pub enum IndexLocation {
+    Url(String),
+    Local(Utf8PathBuf),
+}
+
+#[derive(Debug, Clone)]
+enum IndexSource {
+    /// e.g. https://index.crates.io
+    Sparse(String),
+    /// a local index mirror, or a checkout of a git index
+    Local(Utf8PathBuf),
+}
+
+/// A registry to look up releases of a package.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    pub name: String,
+    index: IndexSource,
+    /// `dl` in the config.json of the index
+    dl: String,
+    /// sent as the `Authorization` header if the registry requires auth
+    token: Option<String>,
+}
+
+/// Resolved registries other than crates.io, keyed by name.
+static REGISTRIES: LazyLock<Mutex<IndexMap<String, Registry>>> = LazyLock::new(Default::default);
+
+impl Registry {
+    pub fn crates_io() -> Self {
+        let source = crate::config::index_source();
+        let index = if source.starts_with("http://") || source.starts_with("https://") {
+            IndexSource::Sparse(source.trim_end_matches('/').to_owned())
+        } else {
+            IndexSource::Local(source.into())
+        };
+        Registry {
+            name: "crates-io".to_owned(),
+            index,
+            dl: "https://static.crates.io/crates".to_owned(),
+            token: None,
+        }
+    }
+
+    /// The first registry in the `publish` field, or crates.io.
+    pub fn for_pkg(pkg: &Package) -> Result<Self> {
+        let name = pkg.publish.as_ref().and_then(|list| list.first());
+        let Some(name) = name.filter(|name| *name != "crates-io") else {
+            return Ok(Self::crates_io());
+        };
+        let mut registries = REGISTRIES.lock().unwrap();
+        if let Some(registry) = registries.get(name) {
+            return Ok(registry.clone());
+        }
+        let dir = pkg.manifest_path.parent().unwrap_or(Utf8Path::new("."));
+        let registry = Self::resolve(name, dir)?;
+        registries.insert(name.clone(), registry.clone());
+        Ok(registry)
+    }
+
+    fn resolve(name: &str, pkg_dir: &Utf8Path) -> Result<Self> {
+        let _span = error_span!("registry", name).entered();
+
+        let url = registry_index(name, &cargo_configs(pkg_dir))
+            .with_context(|| format!("The index of registry `{name}` is not configured."))?;
+        let token = registry_token(name, &credentials());
+        let (index, config) = match url.strip_prefix("sparse+") {
+            Some(url) => {
+                let url = url.trim_end_matches('/').to_owned();
+                // config.json needs the token too if the registry requires auth
+                let config = wget(&format!("{url}/config.json"), token.as_deref())?;
+                (IndexSource::Sparse(url), config)
+            }
+            None => {
+                // a clone for each process, since concurrent runs can't share
+                // a dir they remove and clone over
+                let dir = local_base_dir()
+                    .join("registries")
+                    .join(format!("{name}-{}", std::process::id()));
+                if dir.exists() {
+                    std::fs::remove_dir_all(&dir)?;
+                }
+                info!("git clone {url}");
+                cmd!("git", "clone", "--depth", "1", &url, &dir)
+                    .stdout_null()
+                    .stderr_null()
+                    .run()?;
+                let config = std::fs::read_to_string(dir.join("config.json"))?;
+                (IndexSource::Local(dir), config)
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct Config {
+            dl: String,
+            #[serde(default, rename = "auth-required")]
+            auth_required: bool,
+        }
+        let config: Config = serde_json::from_str(&config)?;
+        ensure!(
+            !config.auth_required || token.is_some(),
+            "Registry `{name}` requires auth: set CARGO_REGISTRIES_{}_TOKEN or \
+             the token in $CARGO_HOME/credentials.toml.",
+            env_name(name)
+        );
+        Ok(Registry {
+            name: name.to_owned(),
+            index,
+            dl: config.dl,
+            token: token.filter(|_| config.auth_required),
+        })
+    }
+
+    /// Read a file from the registry, e.g. an index file.
+    pub fn wget(&self, url: &str) -> Result<String> {
+        wget(url, self.token.as_deref())
+    }
+
+    /// Args of wget to authenticate with the registry.
+    pub fn wget_auth_args(&self) -> Vec<String> {
+        auth_args(self.token.as_deref())
+    }
+
+    pub fn index_location(&self, pkg: &str) -> IndexLocation {
+        let path = index_path(&pkg.to_lowercase());
+        match &self.index {
+            IndexSource::Sparse(url) => IndexLocation::Url(format!("{url}/{path}")),
+            IndexSource::Local(dir) => IndexLocation::Local(dir.join(path)),
+        }
+    }
+
+    /// ref: https://doc.rust-lang.org/cargo/reference/registry-index.html#index-configuration
+    pub fn download_url(&self, pkg: &str, version: &Version, cksum: &str) -> String {
+        const MARKERS: &[&str] = &[
+            "{crate}",
+            "{version}",
+            "{prefix}",
+            "{lowerprefix}",
+            "{sha256-checksum}",
+        ];
+        if !MARKERS.iter().any(|m| self.dl.contains(m)) {
+            // e.g. https://static.crates.io/crates/os-checker/0.4.1/download
+            return format!("{}/{pkg}/{version}/download", self.dl);
+        }
+        let path = index_path(pkg);
+        let prefix = path.rsplit_once('/').map_or("", |(prefix, _)| prefix);
+        self.dl
+            .replace("{crate}", pkg)
+            .replace("{version}", &version.to_string())
+            .replace("{prefix}", prefix)
+            .replace("{lowerprefix}", &prefix.to_lowercase())
+            .replace("{sha256-checksum}", cksum)
+    }
+}
+
+fn auth_args(token: Option<&str>) -> Vec<String> {
+    match token {
+        Some(token) => vec!["--header".to_owned(), format!("Authorization: {token}")],
+        None => Vec::new(),
+    }
+}
+
+fn wget(url: &str, token: Option<&str>) -> Result<String> {
+    info!("wget {url}");
+    let mut args = auth_args(token);
+    args.extend([url.to_owned(), "-O".to_owned(), "-".to_owned()]);
+    let output = duct::cmd("wget", args)
+        .stdout_capture()
+        .stderr_null()
+        .run()?;
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// `NAME_WITH_UNDERSCORES` in env vars like `CARGO_REGISTRIES_<NAME>_INDEX`.
+fn env_name(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Cargo configs from the package dir up to the root, then the one in
+/// `$CARGO_HOME`, in the order of precedence.
+fn cargo_configs(pkg_dir: &Utf8Path) -> Vec<toml::Table> {
+    let cargo_home = cargo_home();
+    let dirs = pkg_dir.ancestors().map(|dir| dir.join(".cargo"));
+    dirs.chain(cargo_home)
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .filter_map(|text| text.parse().ok())
+        .collect()
+}
+
+/// `registries.<name>.index` in cargo configs, which can be overridden by
+/// `CARGO_REGISTRIES_<NAME>_INDEX`.
+fn registry_index(name: &str, configs: &[toml::Table]) -> Option<String> {
+    let env = format!("CARGO_REGISTRIES_{}_INDEX", env_name(name));
+    if let Ok(url) = std::env::var(env) {
+        return Some(url);
+    }
+    registry_key(name, "index", configs)
+}
+
+fn registry_key(name: &str, key: &str, configs: &[toml::Table]) -> Option<String> {
+    configs.iter().find_map(|config| {
+        let value = config.get("registries")?.get(name)?.get(key)?;
+        value.as_str().map(str::to_owned)
+    })
+}
+
+/// `$CARGO_HOME/credentials.toml`, or `credentials` of old cargo.
+fn credentials() -> Vec<toml::Table> {
+    let Some(cargo_home) = cargo_home() else {
+        return Vec::new();
+    };
+    ["credentials.toml", "credentials"]
+        .iter()
+        .filter_map(|file| std::fs::read_to_string(cargo_home.join(file)).ok())
+        .filter_map(|text| text.parse().ok())
+        .collect()
+}
+
+/// `registries.<name>.token` in credentials, which can be overridden by
+/// `CARGO_REGISTRIES_<NAME>_TOKEN`. Credential providers are not supported.
+fn registry_token(name: &str, credentials: &[toml::Table]) -> Option<String> {
+    let env = format!("CARGO_REGISTRIES_{}_TOKEN", env_name(name));
+    if let Ok(token) = std::env::var(env) {
+        return Some(token);
+    }
+    registry_key(name, "token", credentials)
+}
+
+#[test]
+fn resolve_registry() {
+    let configs: Vec<toml::Table> = [
+        "[registries.company]\nindex = \"sparse+https://registry.example.com/index/\"",
+        "[registries.company]\nindex = \"https://git.example.com/index.git\"\n\
+         [registries.other]\nindex = \"https://git.example.com/other.git\"",
+    ]
+    .iter()
+    .map(|text| text.parse().unwrap())
+    .collect();
+    assert_eq!(
+        registry_index("company", &configs).as_deref(),
+        Some("sparse+https://registry.example.com/index/")
+    );
+    assert_eq!(
+        registry_index("other", &configs).as_deref(),
+        Some("https://git.example.com/other.git")
+    );
+    assert_eq!(registry_index("none", &configs), None);
+
+    let version = Version::new(0, 4, 1);
+    let crates_io = Registry::crates_io();
+    assert_eq!(
+        crates_io.download_url("os-checker", &version, ""),
+        "https://static.crates.io/crates/os-checker/0.4.1/download"
+    );
+    let company = Registry {
+        name: "company".to_owned(),
+        index: IndexSource::Sparse("https://registry.example.com/index".to_owned()),
+        dl: "https://dl.example.com/{lowerprefix}/{crate}/{crate}-{version}.crate?sum={sha256-checksum}"
+            .to_owned(),
+        token: None,
+    };
+    assert_eq!(
+        company.download_url("Foo-Bar", &version, "abc"),
+        "https://dl.example.com/fo/o-/Foo-Bar/Foo-Bar-0.4.1.crate?sum=abc"
+    );
+    let IndexLocation::Url(url) = company.index_location("Foo-Bar") else {
+        panic!("a sparse index is expected");
+    };
+    assert_eq!(url, "https://registry.example.com/index/fo/o-/foo-bar");
+
+    let credentials: Vec<toml::Table> =
+        vec!["[registries.company]\ntoken = \"secret\"".parse().unwrap()];
+    let token = registry_token("company", &credentials);
+    assert_eq!(token.as_deref(), Some("secret"));
+    assert_eq!(registry_token("other", &credentials), None);
+    let company = Registry { token, ..company };
+    assert_eq!(
+        company.wget_auth_args(),
+        ["--header", "Authorization: secret"]
+    );
+    assert!(crates_io.wget_auth_args().is_empty());
+}
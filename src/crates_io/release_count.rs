@@ -2,11 +2,13 @@ use cargo_metadata::semver::Version;
 use plugin::prelude::*;
 use serde::Deserialize;
 
-use super::release_tarball::TarballInfo;
-
-fn url(pkg: &str) -> String {
-    const PREFIX: &str = "https://index.crates.io";
+use super::{
+    registry::{IndexLocation, Registry},
+    release_tarball::TarballInfo,
+};
 
+/// The relative path of the index file of a package, e.g. `os/-c/os-checker`.
+pub fn index_path(pkg: &str) -> String {
     // ref: https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files
     let components = match pkg.len() {
         1 => &["1", pkg][..],
@@ -22,21 +24,16 @@ fn url(pkg: &str) -> String {
         }
     };
 
-    // e.g. https://raw.githubusercontent.com/rust-lang/crates.io-index/refs/heads/master/os/-c/os-checker
-    let mut buf = String::with_capacity(128);
-    buf.push_str(PREFIX);
-
-    for c in components {
-        buf.push('/');
-        buf.push_str(c);
-    }
-
-    buf
+    components.join("/")
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Data {
     pub vers: Version,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default)]
+    pub cksum: String,
 }
 
 #[derive(Debug)]
@@ -44,27 +41,42 @@ pub struct IndexFile {
     pub pkg: String,
     pub data: Vec<Data>,
     pub tarball: Option<TarballInfo>,
+    pub registry: Registry,
 }
 
 impl IndexFile {
+    /// Read the index file from crates.io.
+    ///
     /// NOTE: the error may be due to network failure or invalid text
     pub fn new(pkg: &str) -> Result<Self> {
-        let url = url(pkg);
-        info!("wget {url}");
+        Self::with_registry(pkg, Registry::crates_io())
+    }
 
-        let output = duct::cmd!("wget", &url, "-O", "-")
-            .stdout_capture()
-            .stderr_null()
-            .run()?;
+    /// Read the index file from the registry the package is published to.
+    pub fn for_pkg(pkg: &cargo_metadata::Package) -> Result<Self> {
+        Self::with_registry(&pkg.name, Registry::for_pkg(pkg)?)
+    }
 
-        let text = std::str::from_utf8(&output.stdout)?.trim();
+    fn with_registry(pkg: &str, registry: Registry) -> Result<Self> {
+        let text = match registry.index_location(pkg) {
+            IndexLocation::Url(url) => registry.wget(&url)?,
+            IndexLocation::Local(path) => std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read the index file {path}"))?,
+        };
+        let text = text.trim();
         Ok(IndexFile {
             pkg: pkg.to_owned(),
             data: parse_data(text)?,
             tarball: None,
+            registry,
         })
     }
 
+    /// Versions that are not yanked.
+    pub fn available_versions(&self) -> impl Iterator<Item = &Version> {
+        self.data.iter().filter(|d| !d.yanked).map(|d| &d.vers)
+    }
+
     ///  0 is an invalid value because there at least one release if found.
     pub fn release_count(&self) -> usize {
         self.data.len()
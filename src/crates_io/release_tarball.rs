@@ -1,4 +1,4 @@
-use super::IndexFile;
+use super::{tarball_store::TarballStore, IndexFile};
 use crate::repo::local_base_dir;
 use cargo_metadata::semver::Version;
 use eyre::ContextCompat;
@@ -10,52 +10,35 @@ pub struct TarballInfo {
     /// size in bytes
     pub size: u64,
     pub modified: Timestamp,
+    pub version: Version,
+    pub path: Utf8PathBuf,
 }
 
 impl TarballInfo {
-    fn new(tarball: &Utf8Path) -> Result<Self> {
-        let meta = std::fs::metadata(tarball)?;
+    fn new(tarball: Utf8PathBuf, version: &Version) -> Result<Self> {
+        let meta = std::fs::metadata(&tarball)?;
         let size = meta.st_size();
         let modified = Timestamp::from_second(meta.st_mtime())?;
-        Ok(Self { size, modified })
+        Ok(Self {
+            size,
+            modified,
+            version: version.clone(),
+            path: tarball,
+        })
     }
 }
 
-fn download_tarball(pkg: &str, version: &Version) -> Result<Utf8PathBuf> {
-    const TARBALL: &str = "download";
-
-    let url = url(pkg, version);
-    info!("wget {url}");
-
-    let dir = local_base_dir();
-
-    duct::cmd!("wget", url, "-O", TARBALL)
-        .dir(dir)
-        .stdout_null()
-        .stderr_null()
-        .run()?;
-
-    let tarball = dir.join(TARBALL);
-    Ok(tarball)
-}
-
-fn url(pkg: &str, version: &Version) -> String {
-    // wget https://static.crates.io/crates/os-checker/0.4.1/download
-    // for further use: tar xf download && cd os-checker-0.4.1
-    const PREFIX: &str = "https://static.crates.io/crates";
-    format!("{PREFIX}/{pkg}/{version}/download")
-}
-
-fn get_last_release_info(pkg: &str, version: &Version) -> Result<TarballInfo> {
-    let tarball = download_tarball(pkg, version)?;
-    TarballInfo::new(&tarball)
-}
-
 impl IndexFile {
     pub fn get_last_release_info(&mut self) -> Result<()> {
         let last = self.data.last();
         let last = last.with_context(|| "index file is empty")?;
-        self.tarball = Some(get_last_release_info(&self.pkg, &last.vers)?);
+        let url = self
+            .registry
+            .download_url(&self.pkg, &last.vers, &last.cksum);
+        let auth = self.registry.wget_auth_args();
+        let tarball =
+            TarballStore::default().fetch(&self.pkg, &last.vers, &last.cksum, &url, &auth)?;
+        self.tarball = Some(TarballInfo::new(tarball, &last.vers)?);
         Ok(())
     }
 
@@ -64,14 +47,64 @@ impl IndexFile {
             .as_ref()
             .map(|tarball| (tarball.size, tarball.modified))
     }
+
+    /// Unpack the downloaded tarball of the last release into a dir unique to
+    /// this run, and return the dir of the package, e.g.
+    /// `releases/<pid>-<nanos>/os-checker-0.4.1`. The caller removes the
+    /// parent dir after use.
+    pub fn unpack_last_release(&self) -> Result<Utf8PathBuf> {
+        let tarball = self.tarball.as_ref();
+        let tarball = tarball.with_context(|| "the last release is not downloaded")?;
+        let dir = local_base_dir().join("releases").join(format!(
+            "{}-{}",
+            std::process::id(),
+            Timestamp::now().as_nanosecond()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        let pkg_dir = dir.join(format!("{}-{}", self.pkg, tarball.version));
+        duct::cmd!("tar", "xzf", &tarball.path, "-C", &dir)
+            .stdout_null()
+            .stderr_null()
+            .run()?;
+        ensure!(pkg_dir.exists(), "{pkg_dir} is not unpacked");
+        Ok(pkg_dir)
+    }
 }
 
 #[test]
 fn test_tarball_info() -> Result<()> {
-    // test on Cargo.toml instead of tarball for now
-    // dbg!(TarballInfo::new("Cargo.toml".into())?);
+    let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+        .unwrap()
+        .join(format!(
+            "os-checker-test-tarball-{}-{}",
+            std::process::id(),
+            Timestamp::now().as_nanosecond()
+        ));
+    let src = dir.join("foo-0.1.0");
+    std::fs::create_dir_all(&src)?;
+    std::fs::write(src.join("Cargo.toml"), "[package]\nname = \"foo\"\n")?;
+    let path = dir.join("foo-0.1.0.crate");
+    duct::cmd!("tar", "czf", &path, "-C", &dir, "foo-0.1.0").run()?;
+
+    let version = Version::new(0, 1, 0);
+    let index_file = IndexFile {
+        pkg: "foo".to_owned(),
+        data: Vec::new(),
+        tarball: Some(TarballInfo::new(path, &version)?),
+        registry: super::Registry::crates_io(),
+    };
+    let (size, _) = index_file.last_release_size_and_time().unwrap();
+    assert!(size > 0);
 
-    dbg!(get_last_release_info("os-checker", &Version::new(0, 4, 1))?);
+    let pkg_dir = index_file.unpack_last_release()?;
+    assert!(pkg_dir.ends_with("foo-0.1.0"));
+    assert!(pkg_dir.join("Cargo.toml").exists());
+    // another run doesn't share the dir
+    let other = index_file.unpack_last_release()?;
+    assert_ne!(other, pkg_dir);
 
+    for run_dir in [pkg_dir.parent(), other.parent(), Some(&dir)] {
+        std::fs::remove_dir_all(run_dir.unwrap())?;
+    }
     Ok(())
 }
@@ -0,0 +1,197 @@
+use crate::repo::local_base_dir;
+use cargo_metadata::semver::Version;
+use plugin::prelude::*;
+
+/// Downloaded tarballs at `<root>/<crate>/<version>/<cksum>.crate`, so
+/// packages and concurrent runs never share a file, and a tarball is
+/// reused once downloaded.
+///
+/// The access time of a tarball is updated on each fetch to evict the least
+/// recently used ones. The modification time is left as the upload time
+/// from the registry.
+pub struct TarballStore {
+    root: Utf8PathBuf,
+    /// max total size in bytes; the least recently used tarballs are
+    /// evicted beyond it
+    limit: u64,
+}
+
+impl Default for TarballStore {
+    fn default() -> Self {
+        TarballStore {
+            root: local_base_dir().join("tarballs"),
+            limit: crate::config::tarball_store_limit(),
+        }
+    }
+}
+
+impl TarballStore {
+    fn path(&self, pkg: &str, version: &Version, cksum: &str) -> Utf8PathBuf {
+        let cksum = if cksum.is_empty() { "unknown" } else { cksum };
+        self.root
+            .join(pkg)
+            .join(version.to_string())
+            .join(format!("{cksum}.crate"))
+    }
+
+    /// Get the tarball from the store, or download it and verify the
+    /// sha256 checksum from the index. `auth` are wget args to authenticate
+    /// with the registry.
+    pub fn fetch(
+        &self,
+        pkg: &str,
+        version: &Version,
+        cksum: &str,
+        url: &str,
+        auth: &[String],
+    ) -> Result<Utf8PathBuf> {
+        let path = self.path(pkg, version, cksum);
+        if path.exists() {
+            touch(&path)?;
+            return Ok(path);
+        }
+        let dir = path.parent().unwrap();
+        std::fs::create_dir_all(dir)?;
+
+        // download to a unique file and rename it, which is atomic
+        let part = dir.join(format!(
+            "{}-{}.part",
+            std::process::id(),
+            Timestamp::now().as_nanosecond()
+        ));
+        info!("wget {url}");
+        let mut args = auth.to_vec();
+        args.extend([url.to_owned(), "-O".to_owned(), part.to_string()]);
+        let downloaded = duct::cmd("wget", args)
+            .stdout_null()
+            .stderr_null()
+            .run()
+            .map_err(eyre::Report::from)
+            .and_then(|_| verify(&part, cksum));
+        if let Err(err) = downloaded {
+            _ = std::fs::remove_file(&part);
+            return Err(err);
+        }
+        std::fs::rename(&part, &path)?;
+        touch(&path)?;
+
+        if let Err(err) = self.evict(&path) {
+            error!(?err, "Failed to evict tarballs");
+        }
+        Ok(path)
+    }
+
+    /// Remove the least recently used tarballs until the store fits in the
+    /// limit.
+    fn evict(&self, keep: &Utf8Path) -> Result<()> {
+        let mut files: Vec<_> = walkdir::WalkDir::new(&self.root)
+            .into_iter()
+            .flatten()
+            .filter(|e| {
+                e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "crate")
+            })
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                Some((e.into_path(), meta.len(), meta.accessed().ok()?))
+            })
+            .collect();
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        files.sort_unstable_by_key(|(_, _, accessed)| *accessed);
+        for (path, size, _) in files {
+            if total <= self.limit {
+                break;
+            }
+            if path == keep.as_std_path() {
+                continue;
+            }
+            info!(?path, "Evict the tarball");
+            std::fs::remove_file(&path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Set the access time to now, regardless of `noatime` mounts.
+fn touch(path: &Utf8Path) -> Result<()> {
+    let times = std::fs::FileTimes::new().set_accessed(std::time::SystemTime::now());
+    std::fs::File::options()
+        .append(true)
+        .open(path)?
+        .set_times(times)?;
+    Ok(())
+}
+
+/// Check the sha256 of a file with the `cksum` in the index. An empty
+/// cksum is not checked.
+fn verify(path: &Utf8Path, cksum: &str) -> Result<()> {
+    if cksum.is_empty() {
+        return Ok(());
+    }
+    let output = cmd!("sha256sum", path).read()?;
+    let actual = output.split_whitespace().next().unwrap_or_default();
+    ensure!(
+        actual == cksum,
+        "Checksum mismatch for {path}: expected {cksum}, got {actual}"
+    );
+    Ok(())
+}
+
+#[test]
+fn tarball_store() -> Result<()> {
+    let root = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+        .unwrap()
+        .join(format!(
+            "os-checker-plugin-cargo-tarball-store-test-{}-{}",
+            std::process::id(),
+            Timestamp::now().as_nanosecond()
+        ));
+    let store = TarballStore {
+        root: root.clone(),
+        limit: 10,
+    };
+    let version = Version::new(0, 1, 0);
+
+    // sha256 of "hello\n"
+    let cksum = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+    let path = store.path("foo", &version, cksum);
+    assert!(path.ends_with(format!("foo/0.1.0/{cksum}.crate")));
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, "hello\n")?;
+    verify(&path, cksum)?;
+    assert!(verify(&path, "0000").is_err());
+
+    let other = store.path("bar", &version, "");
+    std::fs::create_dir_all(other.parent().unwrap())?;
+    std::fs::write(&other, "hello world\n")?;
+    let accessed = |path: &Utf8Path, secs: u64| -> Result<()> {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        let times = std::fs::FileTimes::new().set_accessed(time);
+        std::fs::File::options()
+            .append(true)
+            .open(path)?
+            .set_times(times)?;
+        Ok(())
+    };
+    accessed(&path, 1)?;
+    accessed(&other, 2)?;
+
+    // reuse without downloading, which makes it the most recently used
+    assert_eq!(
+        store.fetch("foo", &version, cksum, "http://invalid", &[])?,
+        path
+    );
+    store.evict(&store.path("baz", &version, ""))?;
+    assert!(path.exists());
+    assert!(!other.exists());
+
+    // the kept tarball is never evicted
+    accessed(&path, 1)?;
+    std::fs::write(&other, "hello world\n")?;
+    store.evict(&path)?;
+    assert!(path.exists());
+    assert!(!other.exists());
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
@@ -3,6 +3,7 @@ extern crate eyre;
 #[macro_use]
 extern crate tracing;
 
+pub mod config;
 pub mod crates_io;
 pub mod database;
 pub mod nextest;
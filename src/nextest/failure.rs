@@ -0,0 +1,145 @@
+use plugin::prelude::*;
+
+/// Structured information extracted from the output of a failed test.
+#[derive(Debug, Serialize, Default, PartialEq, Eq)]
+pub struct Failure {
+    /// the message following `thread '...' panicked at ...:`
+    pub panic_message: Option<String>,
+    /// `file:line:col` where the test panics
+    pub location: Option<String>,
+    /// the left value of a failed `assert_eq!` or `assert_ne!`
+    pub left: Option<String>,
+    /// the right value of a failed `assert_eq!` or `assert_ne!`
+    pub right: Option<String>,
+    /// the tail of stdout, truncated to the size limit
+    pub stdout_tail: String,
+    /// the tail of stderr, truncated to the size limit
+    pub stderr_tail: String,
+    /// whether stdout or stderr is truncated
+    pub truncated: bool,
+    /// the side file containing the full output
+    pub log: Option<String>,
+}
+
+impl Failure {
+    /// `limit` is the max size in bytes of each of stdout and stderr tails.
+    pub fn new(stdout: &str, stderr: &str, limit: usize) -> Self {
+        let mut failure = Failure::default();
+        // libtest prints the panic message to stderr, but nextest may merge
+        // it into stdout
+        for text in [stderr, stdout] {
+            if let Some(panic) = parse_panic(text) {
+                failure.location = Some(panic.location.to_owned());
+                failure.panic_message = Some(panic.message);
+                failure.left = panic.left.map(str::to_owned);
+                failure.right = panic.right.map(str::to_owned);
+                break;
+            }
+        }
+
+        let (stdout_tail, truncated_stdout) = tail(stdout, limit);
+        let (stderr_tail, truncated_stderr) = tail(stderr, limit);
+        failure.stdout_tail = stdout_tail.to_owned();
+        failure.stderr_tail = stderr_tail.to_owned();
+        failure.truncated = truncated_stdout || truncated_stderr;
+
+        failure
+    }
+}
+
+/// Get the last `limit` bytes of the text on a char boundary, and whether
+/// the text is truncated.
+pub fn tail(text: &str, limit: usize) -> (&str, bool) {
+    if text.len() <= limit {
+        return (text, false);
+    }
+    let mut start = text.len() - limit;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    (&text[start..], true)
+}
+
+struct Panic<'a> {
+    location: &'a str,
+    message: String,
+    left: Option<&'a str>,
+    right: Option<&'a str>,
+}
+
+/// Parse the first panic like
+///
+/// ```text
+/// thread 'tests::eq' (3169) panicked at src/lib.rs:10:5:
+/// assertion `left == right` failed: some message
+///   left: 1
+///  right: 2
+/// note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+/// ```
+fn parse_panic(text: &str) -> Option<Panic<'_>> {
+    const PANICKED_AT: &str = " panicked at ";
+
+    let start = text.lines().position(|line| {
+        line.starts_with("thread '") && line.contains(PANICKED_AT) && line.ends_with(':')
+    })?;
+    let mut lines = text.lines().skip(start);
+
+    let head = lines.next()?;
+    let location = head[head.find(PANICKED_AT)? + PANICKED_AT.len()..].trim_end_matches(':');
+
+    let mut message = Vec::new();
+    let (mut left, mut right) = (None, None);
+    for line in lines {
+        if line.starts_with("note: ") || line.starts_with("stack backtrace:") {
+            break;
+        }
+        if let Some(val) = line.strip_prefix("  left: ") {
+            left = Some(val);
+        } else if let Some(val) = line.strip_prefix(" right: ") {
+            right = Some(val);
+        } else if left.is_none() {
+            // the message may span multiple lines
+            message.push(line);
+        }
+    }
+
+    Some(Panic {
+        location,
+        message: message.join("\n"),
+        left,
+        right,
+    })
+}
+
+#[test]
+fn parse_failure() {
+    let stdout = "
+running 1 test
+thread 'tests::eq' (3169) panicked at src/lib.rs:10:5:
+assertion `left == right` failed: values differ
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+test tests::eq ... FAILED
+";
+    let failure = Failure::new(stdout, "", 21);
+    assert_eq!(failure.location.as_deref(), Some("src/lib.rs:10:5"));
+    assert_eq!(
+        failure.panic_message.as_deref(),
+        Some("assertion `left == right` failed: values differ")
+    );
+    assert_eq!(failure.left.as_deref(), Some("1"));
+    assert_eq!(failure.right.as_deref(), Some("2"));
+    assert_eq!(failure.stdout_tail, "tests::eq ... FAILED\n");
+    assert!(failure.truncated);
+
+    let stderr = "thread 'main' panicked at src/main.rs:2:5:\nexplicit panic\nline 2\n";
+    let failure = Failure::new("", stderr, 1024);
+    assert_eq!(failure.location.as_deref(), Some("src/main.rs:2:5"));
+    assert_eq!(
+        failure.panic_message.as_deref(),
+        Some("explicit panic\nline 2")
+    );
+    assert_eq!(failure.left, None);
+    assert!(!failure.truncated);
+}
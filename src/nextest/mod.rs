@@ -4,17 +4,40 @@ use plugin::prelude::*;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::hash::Hash;
 
+mod failure;
+pub use failure::{tail, Failure};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReportTest {
     #[serde(rename = "type")]
     typ: TypeTest,
     event: Event,
-    name: Name,
+    name: AttemptName,
     /// execution time in seconds; Some for Event::ok
     exec_time: Option<f32>,
     /// running error: None means no error
     #[serde(default, deserialize_with = "strip_color")]
     stdout: Option<String>,
+    /// only present if nextest doesn't merge it into stdout
+    #[serde(default, deserialize_with = "strip_color")]
+    stderr: Option<String>,
+}
+
+/// A suite event emitted when a test binary starts or finishes.
+#[derive(Debug, Deserialize)]
+pub struct ReportSuite {
+    #[serde(rename = "type")]
+    typ: String,
+    /// execution time in seconds of the whole test binary; Some when it finishes
+    exec_time: Option<f32>,
+    nextest: SuiteInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct SuiteInfo {
+    #[serde(rename = "crate")]
+    pkg_name: String,
+    test_binary: String,
 }
 
 fn strip_color<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
@@ -49,8 +72,7 @@ pub enum Event {
     Ignored,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
-#[serde(from = "&str")]
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Name {
     pkg_name: String,
     test_binary: String,
@@ -70,9 +92,35 @@ impl Equivalent<Name> for [&'_ str; 3] {
     }
 }
 
+impl Name {
+    pub fn as_array(&self) -> [&str; 3] {
+        [&self.pkg_name, &self.test_binary, &self.test_case]
+    }
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Name {
+            pkg_name,
+            test_binary,
+            test_case,
+        } = self;
+        write!(f, "{pkg_name}::{test_binary}${test_case}")
+    }
+}
+
+/// A test name along with the attempt it's reported for.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "&str")]
+pub struct AttemptName {
+    name: Name,
+    /// starts from 1; greater than 1 if the test is retried
+    attempt: u32,
+}
+
 // pkg-name::test_binary_name$testcase_path#n
-// #n is an optional suffix if the test was retried for n times (ignored for now)
-impl From<&'_ str> for Name {
+// #n is an optional suffix if the test was retried, i.e. the n-th attempt
+impl From<&'_ str> for AttemptName {
     fn from(mut text: &'_ str) -> Self {
         let pkg_name_end = text.find(':').unwrap();
         let pkg_name = text[..pkg_name_end].to_owned();
@@ -87,10 +135,18 @@ impl From<&'_ str> for Name {
         let test_case_end = text.find('#').unwrap_or(text.len());
         let test_case = text[..test_case_end].to_owned();
 
-        Name {
-            pkg_name,
-            test_binary,
-            test_case,
+        let attempt = text
+            .get(test_case_end + 1..)
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1);
+
+        AttemptName {
+            name: Name {
+                pkg_name,
+                test_binary,
+                test_case,
+            },
+            attempt,
         }
     }
 }
@@ -98,12 +154,15 @@ impl From<&'_ str> for Name {
 #[test]
 fn string_to_name() {
     let text = "os-checker-plugin-cargo::os_checker_plugin_cargo$repo::test_cargo_tomls";
-    let name = Name::from(text);
+    let name = AttemptName::from(text);
     dbg!(&name);
+    assert_eq!(name.attempt, 1);
 
     let text_retry = "os-checker-plugin-cargo::os_checker_plugin_cargo$repo::test_cargo_tomls#2";
-    let name = Name::from(text_retry);
-    dbg!(&name);
+    let retry = AttemptName::from(text_retry);
+    dbg!(&retry);
+    assert_eq!(retry.name, name.name);
+    assert_eq!(retry.attempt, 2);
 }
 
 #[test]
@@ -121,42 +180,154 @@ fn parse_stream() {
     assert!(!reports.is_empty());
 }
 
+#[test]
+fn flaky_retries() {
+    let text = r#"{"type":"test","event":"started","name":"pkg::bin$flaky"}
+{"type":"test","event":"failed","name":"pkg::bin$flaky","exec_time":0.1,"stdout":"boom"}
+{"type":"test","event":"started","name":"pkg::bin$flaky#2"}
+{"type":"test","event":"ok","name":"pkg::bin$flaky#2","exec_time":0.2}
+{"type":"test","event":"started","name":"pkg::bin$stable"}
+{"type":"test","event":"ok","name":"pkg::bin$stable","exec_time":0.3}"#;
+    let records = collect_records(parse_test_reports(text));
+    assert_eq!(records.len(), 2);
+
+    let flaky = &records[&["pkg", "bin", "flaky"]];
+    assert!(flaky.is_flaky());
+    assert_eq!(flaky.attempts.len(), 2);
+    assert_eq!(flaky.stdout, None);
+    assert!(flaky.failure(1024).is_none());
+    assert_eq!(flaky.flaky_stdout.as_deref(), Some("boom"));
+    assert!(flaky.flaky_failure(1024).is_some());
+    assert_eq!(flaky.duration_ms(), Some(200));
+
+    let stable = &records[&["pkg", "bin", "stable"]];
+    assert!(!stable.is_flaky());
+    assert_eq!(stable.attempts.len(), 1);
+}
+
 fn parse_test_reports(text: &str) -> Vec<ReportTest> {
     text.lines()
         .filter_map(|line| serde_json::from_str::<ReportTest>(line).ok())
         .collect()
 }
 
+/// `Map<"pkg::test_binary", exec_time_ms>`; the last finished suite wins.
+fn parse_suite_times(text: &str) -> IndexMap<String, u32> {
+    text.lines()
+        .filter_map(|line| serde_json::from_str::<ReportSuite>(line).ok())
+        .filter(|suite| suite.typ == "suite")
+        .filter_map(|suite| {
+            let SuiteInfo {
+                pkg_name,
+                test_binary,
+            } = suite.nextest;
+            Some((
+                format!("{pkg_name}::{test_binary}"),
+                sec_to_ms(suite.exec_time)?,
+            ))
+        })
+        .collect()
+}
+
+#[test]
+fn parse_suites() {
+    let text = std::fs::read_to_string("tests/nextest.stdout").unwrap();
+    let suites = parse_suite_times(&text);
+    dbg!(&suites);
+    assert_eq!(suites["os-checker-plugin-cargo::t1"], 5);
+}
+
 pub fn run_testcases(ws_dir: &Utf8Path) -> Result<Report> {
-    let output = duct::cmd!(
-        "cargo",
+    let retries = crate::config::nextest_retries();
+    run_testcases_with(ws_dir, &["--retries", &retries.to_string()], &[])
+}
+
+/// Run tests with extra nextest arguments and environment variables.
+pub fn run_testcases_with(
+    ws_dir: &Utf8Path,
+    extra_args: &[&str],
+    envs: &[(&str, &str)],
+) -> Result<Report> {
+    let mut args = vec![
         "nextest",
         "run",
         "--workspace",
         "--no-fail-fast",
         "--color=never",
         "--message-format",
-        "libtest-json-plus"
-    )
-    .env("NEXTEST_EXPERIMENTAL_LIBTEST_JSON", "1")
-    .stdout_capture()
-    .stderr_capture()
-    .unchecked()
-    .dir(ws_dir)
-    .run()?;
+        "libtest-json-plus",
+    ];
+    args.extend(extra_args);
+
+    let mut expr = duct::cmd("cargo", args).env("NEXTEST_EXPERIMENTAL_LIBTEST_JSON", "1");
+    for (key, val) in envs {
+        expr = expr.env(key, val);
+    }
+
+    let now = std::time::Instant::now();
+    let output = expr
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .dir(ws_dir)
+        .run()?;
+
+    let wall_ms = now.elapsed().as_millis() as u64;
 
     let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
 
-    let reports = parse_test_reports(std::str::from_utf8(&output.stdout)?);
-    // new event overrides old ones:
-    // e.g. if a test result is ok, we won't get its started report
-    let testcases: IndexMap<_, _> = reports
-        .into_iter()
-        .map(|report| (report.name, (report.event, report.exec_time, report.stdout)))
-        .collect();
+    let stdout = std::str::from_utf8(&output.stdout)?;
+    let testcases = collect_records(parse_test_reports(stdout));
     debug!(testcases.len = testcases.len());
+    let suite_times = parse_suite_times(stdout);
+
+    Ok(Report {
+        stderr,
+        testcases,
+        suite_times,
+        wall_ms,
+    })
+}
+
+fn collect_records(reports: Vec<ReportTest>) -> IndexMap<Name, TestRecord> {
+    let mut testcases = IndexMap::<Name, TestRecord>::new();
+    for report in reports {
+        let AttemptName { name, attempt } = report.name;
+        let record = testcases.entry(name).or_insert_with(|| TestRecord {
+            event: report.event,
+            exec_time: None,
+            stdout: None,
+            stderr: None,
+            flaky_stdout: None,
+            flaky_stderr: None,
+            attempts: Vec::new(),
+        });
+        // new event overrides old ones:
+        // e.g. if a test result is ok, we won't get its started report
+        record.event = report.event;
+        record.exec_time = report.exec_time;
+        if report.event != Event::Started {
+            // the output of a failed attempt is kept apart in case a retry
+            // passes
+            if report.event == Event::Failed {
+                record.flaky_stdout.clone_from(&report.stdout);
+                record.flaky_stderr.clone_from(&report.stderr);
+            }
+            record.stdout = report.stdout;
+            record.stderr = report.stderr;
+            record.attempts.push(Attempt {
+                attempt,
+                status: report.event,
+                duration_ms: sec_to_ms(report.exec_time),
+            });
+        }
+    }
+    testcases
+}
 
-    Ok(Report { stderr, testcases })
+/// second => millisecond
+fn sec_to_ms(t: Option<f32>) -> Option<u32> {
+    t.map(|f| (f * 1000.0).round() as u32)
 }
 
 pub struct Report {
@@ -164,22 +335,85 @@ pub struct Report {
     /// FIXME: 尚未考虑 binary kind，也就是说，如果同名测试函数路径存在于 lib 和 bin，它们的数据不正确。
     /// 如果要知道 binary kind，需要解析 suite type 消息，并依赖于解析整个消息。
     /// 目前只读取 test type 消息，解析单个消息。
-    pub testcases: IndexMap<Name, (Event, Option<f32>, Option<String>)>,
+    pub testcases: IndexMap<Name, TestRecord>,
+    /// `Map<"pkg::test_binary", exec_time_ms>`
+    pub suite_times: IndexMap<String, u32>,
+    /// wall time of `cargo nextest run` for the workspace, including the build
+    pub wall_ms: u64,
 }
 
-impl Report {
-    pub fn get_test_case(
-        &self,
-        pkg_bin_test: &[&str; 3],
-    ) -> (Option<Event>, Option<u32>, Option<String>) {
-        match self.testcases.get(pkg_bin_test) {
-            Some((e, t, stdout)) => (
-                Some(*e),
-                t.map(|f| (f * 1000.0).round() as u32),
-                stdout.clone(),
-            ), // second => millisecond
-            None => (None, None, None),
+/// Test result merged from all attempts of a test.
+#[derive(Debug)]
+pub struct TestRecord {
+    /// the event of the last attempt
+    pub event: Event,
+    /// execution time in seconds of the last attempt
+    pub exec_time: Option<f32>,
+    /// output of the last attempt
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    /// output of the last failed attempt, used if a retry passes
+    flaky_stdout: Option<String>,
+    flaky_stderr: Option<String>,
+    /// finished attempts in order; more than one if the test is retried
+    pub attempts: Vec<Attempt>,
+}
+
+impl TestRecord {
+    pub fn duration_ms(&self) -> Option<u32> {
+        sec_to_ms(self.exec_time)
+    }
+
+    /// Structured failure from the output; None if there's no output.
+    pub fn failure(&self, limit: usize) -> Option<Failure> {
+        if self.stdout.is_none() && self.stderr.is_none() {
+            return None;
+        }
+        let stdout = self.stdout.as_deref().unwrap_or_default();
+        let stderr = self.stderr.as_deref().unwrap_or_default();
+        Some(Failure::new(stdout, stderr, limit))
+    }
+
+    /// Structured failure of the last failed attempt if the test is flaky.
+    pub fn flaky_failure(&self, limit: usize) -> Option<Failure> {
+        if !self.is_flaky() {
+            return None;
         }
+        let stdout = self.flaky_stdout.as_deref().unwrap_or_default();
+        let stderr = self.flaky_stderr.as_deref().unwrap_or_default();
+        Some(Failure::new(stdout, stderr, limit))
+    }
+
+    /// The full output as is.
+    pub fn full_output(&self) -> String {
+        let stdout = self.stdout.as_deref().unwrap_or_default();
+        match self.stderr.as_deref() {
+            Some(stderr) => format!("{stdout}\n---- stderr ----\n{stderr}"),
+            None => stdout.to_owned(),
+        }
+    }
+
+    /// A test is flaky if it fails at first but passes on a retry.
+    pub fn is_flaky(&self) -> bool {
+        self.event == Event::Ok && self.attempts.iter().any(|a| a.status == Event::Failed)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+pub struct Attempt {
+    pub attempt: u32,
+    pub status: Event,
+    pub duration_ms: Option<u32>,
+}
+
+impl Report {
+    pub fn get_test_case(&self, pkg_bin_test: &[&str; 3]) -> Option<&TestRecord> {
+        self.testcases.get(pkg_bin_test)
+    }
+
+    /// Wall-clock time in ms of running a test binary.
+    pub fn suite_time_ms(&self, pkg: &str, bin: &str) -> Option<u32> {
+        self.suite_times.get(&format!("{pkg}::{bin}")).copied()
     }
 }
 
@@ -191,6 +425,7 @@ fn run_and_parse() -> Result<()> {
     let Report {
         stderr: _,
         testcases,
+        ..
     } = run_testcases(Utf8Path::new("."))?;
 
     // println!("stderr={stderr}\ntestcases={testcases:?}");
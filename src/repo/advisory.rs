@@ -0,0 +1,253 @@
+use super::deps::dep_packages;
+use cargo_metadata::{
+    semver::{Version, VersionReq},
+    Package,
+};
+use plugin::prelude::*;
+use std::sync::LazyLock;
+
+/// Advisories in the local RustSec database, keyed by crate name.
+type AdvisoryDb = IndexMap<String, Vec<Advisory>>;
+
+static DB: LazyLock<Option<AdvisoryDb>> = LazyLock::new(|| {
+    let path = crate::config::advisory_db()?;
+    load_db(Utf8Path::new(&path))
+        .inspect_err(|err| error!(?err, path, "Failed to load the advisory database"))
+        .ok()
+});
+
+/// An advisory from `crates/<name>/RUSTSEC-*.md` in the RustSec database.
+#[derive(Debug)]
+struct Advisory {
+    id: String,
+    package: String,
+    title: String,
+    informational: Option<String>,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+}
+
+#[derive(Deserialize)]
+struct FrontMatter {
+    advisory: AdvisoryMeta,
+    #[serde(default)]
+    versions: Versions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMeta {
+    id: String,
+    package: String,
+    informational: Option<String>,
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct Versions {
+    #[serde(default)]
+    patched: Vec<VersionReq>,
+    #[serde(default)]
+    unaffected: Vec<VersionReq>,
+}
+
+/// Parse an advisory in markdown with toml front matter:
+///
+/// ````text
+/// ```toml
+/// [advisory]
+/// id = "RUSTSEC-2020-0001"
+/// package = "foo"
+/// [versions]
+/// patched = [">= 1.2.3"]
+/// ```
+///
+/// # Title of the advisory
+/// ````
+///
+/// Returns None for withdrawn advisories.
+fn parse_advisory(text: &str) -> Result<Option<Advisory>> {
+    let rest = text
+        .trim_start()
+        .strip_prefix("```toml")
+        .with_context(|| "No toml front matter in the advisory.")?;
+    let (front, body) = rest
+        .split_once("```")
+        .with_context(|| "Unclosed toml front matter in the advisory.")?;
+    let front: FrontMatter = toml::from_str(front)?;
+    if front.advisory.withdrawn.is_some() {
+        return Ok(None);
+    }
+    let title = body
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .unwrap_or_default()
+        .trim()
+        .to_owned();
+    Ok(Some(Advisory {
+        id: front.advisory.id,
+        package: front.advisory.package,
+        title,
+        informational: front.advisory.informational,
+        patched: front.versions.patched,
+        unaffected: front.versions.unaffected,
+    }))
+}
+
+fn load_db(path: &Utf8Path) -> Result<AdvisoryDb> {
+    let dir = path.join("crates");
+    ensure!(dir.is_dir(), "{dir} is not a directory.");
+    let mut db = AdvisoryDb::new();
+    for entry in walkdir::WalkDir::new(&dir).into_iter().flatten() {
+        let file = entry.path();
+        if file.extension().is_none_or(|ext| ext != "md") {
+            continue;
+        }
+        let text = std::fs::read_to_string(file)?;
+        match parse_advisory(&text) {
+            Ok(Some(advisory)) => db
+                .entry(advisory.package.clone())
+                .or_default()
+                .push(advisory),
+            Ok(None) => (),
+            Err(err) => warn!(?err, ?file, "Skip an invalid advisory"),
+        }
+    }
+    info!(crates = db.len(), "Loaded the advisory database");
+    Ok(db)
+}
+
+/// A dependency matched by an advisory.
+#[derive(Debug, Serialize)]
+pub struct AdvisoryMatch {
+    pub id: String,
+    pub package: String,
+    pub version: String,
+    pub title: String,
+    /// versions without the issue
+    pub patched: Vec<String>,
+}
+
+/// RustSec advisories and yanked releases in dependencies of a package.
+#[derive(Debug, Default, Serialize)]
+pub struct AdvisoryReport {
+    pub vulnerable: Vec<AdvisoryMatch>,
+    pub unmaintained: Vec<AdvisoryMatch>,
+    pub unsound: Vec<AdvisoryMatch>,
+    /// `name@version` of yanked releases, looked up in the local index cache
+    pub yanked: Vec<String>,
+}
+
+impl AdvisoryReport {
+    /// Check registry dependencies in the resolved lockfile against the
+    /// database. Dev-dependencies are excluded, as in the unsafe inventory.
+    pub fn new(pkg: &Package, meta: &Metadata) -> Option<Self> {
+        let db = DB.as_ref()?;
+        let mut report = AdvisoryReport::default();
+        for dep in dep_packages(pkg, meta) {
+            if !dep.source.as_ref().is_some_and(|s| s.is_crates_io()) {
+                continue;
+            }
+            for advisory in db.get(dep.name.as_str()).into_iter().flatten() {
+                if !advisory.affects(&dep.version) {
+                    continue;
+                }
+                let list = match advisory.informational.as_deref() {
+                    None => &mut report.vulnerable,
+                    Some("unmaintained") => &mut report.unmaintained,
+                    Some("unsound") => &mut report.unsound,
+                    // e.g. notice
+                    Some(_) => continue,
+                };
+                list.push(AdvisoryMatch {
+                    id: advisory.id.clone(),
+                    package: dep.name.clone(),
+                    version: dep.version.to_string(),
+                    title: advisory.title.clone(),
+                    patched: advisory.patched.iter().map(|req| req.to_string()).collect(),
+                });
+            }
+            if yanked(&dep.name, &dep.version) {
+                report.yanked.push(format!("{}@{}", dep.name, dep.version));
+            }
+        }
+        Some(report)
+    }
+}
+
+/// Whether the release is yanked according to the sparse index cache that
+/// cargo keeps in `$CARGO_HOME/registry/index`. Unknown releases are
+/// treated as not yanked, since no network access is made.
+fn yanked(name: &str, version: &Version) -> bool {
+    let cargo_home = super::cargo_home().unwrap_or_default();
+    let index_dir = cargo_home.join("registry").join("index");
+    let Ok(dirs) = index_dir.read_dir_utf8() else {
+        return false;
+    };
+    let path = crate::crates_io::index_path(&name.to_lowercase());
+    dirs.flatten()
+        .filter(|dir| dir.file_name().starts_with("index.crates.io-"))
+        .filter_map(|dir| std::fs::read(dir.path().join(".cache").join(&path)).ok())
+        .any(|cache| cache_yanked(&cache, version))
+}
+
+#[derive(Deserialize)]
+struct CacheEntry {
+    vers: Version,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// The cache file has a binary header followed by NUL-separated pairs of a
+/// version and its json line in the index.
+fn cache_yanked(cache: &[u8], version: &Version) -> bool {
+    cache
+        .split(|b| *b == 0)
+        .filter(|field| field.first() == Some(&b'{'))
+        .filter_map(|field| serde_json::from_slice::<CacheEntry>(field).ok())
+        .any(|entry| entry.vers == *version && entry.yanked)
+}
+
+#[test]
+fn parse_advisory_db_entry() -> Result<()> {
+    let text = r#"```toml
+[advisory]
+id = "RUSTSEC-2021-0001"
+package = "foo"
+date = "2021-01-01"
+categories = ["memory-corruption"]
+
+[versions]
+patched = [">= 1.2.3"]
+unaffected = ["< 0.5.0"]
+```
+
+# Use after free in `Foo::bar`
+
+Details.
+"#;
+    let advisory = parse_advisory(text)?.unwrap();
+    assert_eq!(advisory.id, "RUSTSEC-2021-0001");
+    assert_eq!(advisory.title, "Use after free in `Foo::bar`");
+    assert_eq!(advisory.informational, None);
+    assert!(advisory.affects(&Version::new(1, 0, 0)));
+    assert!(!advisory.affects(&Version::new(1, 2, 3)));
+    assert!(!advisory.affects(&Version::new(0, 4, 0)));
+
+    let withdrawn = text.replace("date =", "withdrawn = \"2021-02-01\"\ndate =");
+    assert!(parse_advisory(&withdrawn)?.is_none());
+
+    let cache = b"\x03\x02\0\0\0etag\x000.1.0\0{\"vers\":\"0.1.0\",\"yanked\":true}\x000.2.0\0{\"vers\":\"0.2.0\",\"yanked\":false}\0";
+    assert!(cache_yanked(cache, &Version::new(0, 1, 0)));
+    assert!(!cache_yanked(cache, &Version::new(0, 2, 0)));
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use cargo_metadata::{diagnostic::DiagnosticLevel, Message};
+use nextest_metadata::TestListSummary;
+use plugin::prelude::*;
+
+/// Whether test binaries of a package compile.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BuildStatus {
+    Ok,
+    Failed {
+        errors: Vec<BuildError>,
+    },
+    /// the build fails or can't run, but no error is attributed to the
+    /// package, e.g. a linker error or a failure in another package
+    Unknown {
+        reason: String,
+    },
+}
+
+/// A compilation error emitted when building test binaries.
+#[derive(Debug, Serialize)]
+pub struct BuildError {
+    /// e.g. E0308; None for errors without a code
+    pub code: Option<String>,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    /// the target being compiled, e.g. `lib` or `test t1`
+    pub target: String,
+}
+
+pub type BuildErrors = IndexMap<String, Vec<BuildError>>;
+
+/// Compile time of a unit, like `cargo build --timings`.
+#[derive(Debug, Serialize)]
+pub struct UnitTime {
+    /// e.g. `lib` or `test t1`
+    pub target: String,
+    /// e.g. `build` or `test`
+    pub mode: String,
+    pub duration_ms: u64,
+}
+
+/// Build time of test binaries of a package.
+#[derive(Debug, Serialize)]
+pub struct BuildTime {
+    /// units of the package, excluding dependencies; None if timings are
+    /// unavailable
+    pub units: Option<Vec<UnitTime>>,
+    /// sum of compile time of the units; None if timings are unavailable
+    pub total_ms: Option<u64>,
+    /// wall time to list tests of the whole workspace, which builds test
+    /// binaries including dependencies
+    pub workspace_wall_ms: u64,
+}
+
+/// Result of building test binaries in a workspace.
+#[derive(Debug, Default)]
+pub struct TestBuild {
+    /// whether `cargo nextest list` exits successfully
+    pub success: bool,
+    /// whether the build is run with `--timings=json`
+    pub timings: bool,
+    pub errors: BuildErrors,
+    pub units: IndexMap<String, Vec<UnitTime>>,
+    pub wall_ms: u64,
+}
+
+impl TestBuild {
+    pub fn build_status(&mut self, pkg: &str) -> BuildStatus {
+        match self.errors.swap_remove(pkg) {
+            Some(errors) => BuildStatus::Failed { errors },
+            None if self.success => BuildStatus::Ok,
+            None => BuildStatus::Unknown {
+                reason: "building test binaries fails without errors in this package".to_owned(),
+            },
+        }
+    }
+
+    pub fn build_time(&mut self, pkg: &str) -> BuildTime {
+        let units = self.units.swap_remove(pkg).unwrap_or_default();
+        let units = self.timings.then_some(units);
+        BuildTime {
+            total_ms: units
+                .as_ref()
+                .map(|units| units.iter().map(|u| u.duration_ms).sum()),
+            units,
+            workspace_wall_ms: self.wall_ms,
+        }
+    }
+}
+
+/// List tests with `cargo nextest list`, which builds test binaries, and
+/// collect compilation errors and compile time for each package from the
+/// cargo messages nextest forwards. The test list is an error if the build
+/// fails.
+///
+/// Timings need `-Zunstable-options`; if the toolchain rejects it, list
+/// without timings.
+pub fn list_tests(
+    workspace_root: &Utf8Path,
+    meta: &Metadata,
+) -> (TestBuild, Result<TestListSummary>) {
+    let _span = error_span!("list_tests", ?workspace_root).entered();
+
+    let timings = ["-Zunstable-options", "--timings=json"];
+    let now = std::time::Instant::now();
+    let mut output = nextest_list(workspace_root, &timings);
+    let mut with_timings = true;
+    if output
+        .as_ref()
+        .is_ok_and(|o| !o.status.success() && o.stdout.is_empty())
+    {
+        warn!("List tests without timings");
+        output = nextest_list(workspace_root, &[]);
+        with_timings = false;
+    }
+    let wall_ms = now.elapsed().as_millis() as u64;
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => return (TestBuild::default(), Err(err)),
+    };
+    // cargo messages may be forwarded to either stream
+    let mut build = parse_build_messages(&[&*output.stdout, &*output.stderr].concat(), meta);
+    build.success = output.status.success();
+    build.timings = with_timings;
+    build.wall_ms = wall_ms;
+
+    // the test list is a line of json after cargo messages
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let list = stdout
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str::<TestListSummary>(line).ok())
+        .with_context(|| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            format!("fail to run `cargo nextest list` in {workspace_root}:\n{stderr}")
+        });
+    (build, list)
+}
+
+fn nextest_list(workspace_root: &Utf8Path, extra_args: &[&str]) -> Result<std::process::Output> {
+    let mut command = nextest_metadata::ListCommand::new();
+    command
+        .current_dir(workspace_root)
+        .add_args(["--cargo-message-format", "json"])
+        .add_args(extra_args.iter().copied());
+    Ok(command.cargo_command().output()?)
+}
+
+/// `{"reason":"timing-info","package_id":"...","target":{...},"mode":"test","duration":0.5}`
+#[derive(Deserialize)]
+struct TimingInfo {
+    reason: String,
+    package_id: cargo_metadata::PackageId,
+    target: cargo_metadata::Target,
+    mode: String,
+    /// in seconds
+    duration: f64,
+}
+
+fn target_name(target: &cargo_metadata::Target) -> String {
+    format!("{} {}", target.kind.join(","), target.name)
+}
+
+fn parse_build_messages(stdout: &[u8], meta: &Metadata) -> TestBuild {
+    let pkg_name = |id: &cargo_metadata::PackageId| {
+        let pkg = meta.packages.iter().find(|p| p.id == *id)?;
+        Some(pkg.name.clone())
+    };
+
+    let mut build = TestBuild::default();
+    for message in Message::parse_stream(stdout).flatten() {
+        match message {
+            Message::CompilerMessage(msg) => {
+                let diag = &msg.message;
+                if !matches!(diag.level, DiagnosticLevel::Error | DiagnosticLevel::Ice) {
+                    continue;
+                }
+                let Some(pkg) = pkg_name(&msg.package_id) else {
+                    continue;
+                };
+                let span = diag.spans.iter().find(|s| s.is_primary);
+                let error = BuildError {
+                    code: diag.code.as_ref().map(|c| c.code.clone()),
+                    message: diag.message.clone(),
+                    file: span.map(|s| s.file_name.clone()),
+                    line: span.map(|s| s.line_start),
+                    target: target_name(&msg.target),
+                };
+                build.errors.entry(pkg).or_default().push(error);
+            }
+            // timing-info is unknown to cargo_metadata
+            Message::TextLine(line) => {
+                let Ok(info) = serde_json::from_str::<TimingInfo>(&line) else {
+                    continue;
+                };
+                if info.reason != "timing-info" {
+                    continue;
+                }
+                let Some(pkg) = pkg_name(&info.package_id) else {
+                    continue;
+                };
+                let unit = UnitTime {
+                    target: target_name(&info.target),
+                    mode: info.mode,
+                    duration_ms: (info.duration * 1000.0).round() as u64,
+                };
+                build.units.entry(pkg).or_default().push(unit);
+            }
+            _ => (),
+        }
+    }
+    build
+}
+
+#[test]
+fn parse_compiler_messages() -> Result<()> {
+    let meta = super::fixture_metadata();
+    let pkg = meta.root_package().unwrap();
+    let line = serde_json::json!({
+        "reason": "compiler-message",
+        "package_id": pkg.id,
+        "manifest_path": pkg.manifest_path,
+        "target": {
+            "name": "it", "kind": ["test"], "crate_types": ["bin"],
+            "src_path": "tests/it.rs", "edition": "2021", "doctest": false, "test": true
+        },
+        "message": {
+            "message": "mismatched types",
+            "code": { "code": "E0308", "explanation": null },
+            "level": "error",
+            "spans": [{
+                "file_name": "tests/it.rs", "byte_start": 0, "byte_end": 1,
+                "line_start": 3, "line_end": 3, "column_start": 1, "column_end": 2,
+                "is_primary": true, "text": [], "label": null,
+                "suggested_replacement": null, "suggestion_applicability": null,
+                "expansion": null
+            }],
+            "children": [],
+            "rendered": null
+        }
+    });
+    let timing = serde_json::json!({
+        "reason": "timing-info",
+        "package_id": pkg.id,
+        "target": line["target"],
+        "mode": "test",
+        "duration": 1.5,
+        "rmeta_time": null
+    });
+    let stdout = format!("{line}\n{timing}\n");
+    let mut build = parse_build_messages(stdout.as_bytes(), &meta);
+    build.timings = true;
+    let error = &build.errors["app"][0];
+    assert_eq!(error.code.as_deref(), Some("E0308"));
+    assert_eq!(error.message, "mismatched types");
+    assert_eq!(error.file.as_deref(), Some("tests/it.rs"));
+    assert_eq!(error.line, Some(3));
+    assert_eq!(error.target, "test it");
+    let time = build.build_time("app");
+    assert_eq!(time.units.unwrap()[0].target, "test it");
+    assert_eq!(time.total_ms, Some(1500));
+    assert!(matches!(
+        build.build_status("app"),
+        BuildStatus::Failed { .. }
+    ));
+    // the build fails without errors of this package
+    assert!(matches!(
+        build.build_status("app"),
+        BuildStatus::Unknown { .. }
+    ));
+
+    build.timings = false;
+    assert_eq!(build.build_time("app").total_ms, None);
+    Ok(())
+}
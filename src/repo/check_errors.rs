@@ -0,0 +1,51 @@
+use crate::nextest::tail;
+
+/// Ok if `cargo check` succeeds, otherwise the tail of its error
+/// diagnostics.
+pub fn check_result(output: &std::process::Output) -> Result<(), String> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let errors = error_lines(&stderr);
+    Err(tail(&errors, crate::config::output_limit()).0.to_owned())
+}
+
+/// Keep error diagnostics and drop progress lines like `Compiling` and
+/// warnings.
+fn error_lines(stderr: &str) -> String {
+    let mut errors = String::new();
+    let mut in_error = false;
+    for line in stderr.lines() {
+        if line.starts_with("error") {
+            in_error = true;
+        } else if line.starts_with("warning") || line.trim_start().starts_with("Compiling ") {
+            in_error = false;
+        }
+        if in_error {
+            errors.push_str(line);
+            errors.push('\n');
+        }
+    }
+    if errors.is_empty() {
+        stderr.to_owned()
+    } else {
+        errors
+    }
+}
+
+#[test]
+fn check_errors() {
+    let stderr = "   Compiling foo v0.1.0
+warning: unused import
+ --> src/lib.rs:1:5
+error[E0658]: use of unstable library feature `let_chains`
+ --> src/lib.rs:3:8
+  |
+error: could not compile `foo` (lib) due to 1 previous error
+";
+    let errors = error_lines(stderr);
+    assert!(errors.starts_with("error[E0658]"));
+    assert!(!errors.contains("unused import"));
+    assert_eq!(errors.lines().count(), 4);
+}
@@ -0,0 +1,241 @@
+use cargo_metadata::{Message, Package};
+use plugin::prelude::*;
+use std::process::Command;
+
+/// Covered and total amounts of lines, functions or regions.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Counts {
+    pub count: u64,
+    pub covered: u64,
+    pub percent: f64,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.count += other.count;
+        self.covered += other.covered;
+        self.percent = if self.count == 0 {
+            0.0
+        } else {
+            self.covered as f64 * 100.0 / self.count as f64
+        };
+    }
+}
+
+/// Code coverage of a package by its nextest suite.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Coverage {
+    pub lines: Counts,
+    pub functions: Counts,
+    pub regions: Counts,
+}
+
+impl Coverage {
+    fn add(&mut self, other: &Coverage) {
+        self.lines.add(&other.lines);
+        self.functions.add(&other.functions);
+        self.regions.add(&other.regions);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WorkspaceCoverage {
+    pub pkgs: IndexMap<String, Coverage>,
+    /// lcov report of the workspace
+    pub lcov: String,
+}
+
+/// Build with `-C instrument-coverage`, run the nextest suite, merge the
+/// profiles and export coverage for each package.
+///
+/// A separate target dir is used to not invalidate the normal build.
+pub fn run(workspace_root: &Utf8Path, meta: &Metadata) -> Result<WorkspaceCoverage> {
+    let _span = error_span!("coverage", ?workspace_root).entered();
+
+    let target_dir = meta.target_directory.join("os-checker-coverage");
+    let profile_dir = target_dir.join("profraw");
+    if profile_dir.exists() {
+        std::fs::remove_dir_all(&profile_dir)?;
+    }
+    std::fs::create_dir_all(&profile_dir)?;
+
+    let env = |cmd: duct::Expression| {
+        cmd.dir(workspace_root)
+            .env("RUSTFLAGS", "-C instrument-coverage")
+            .env("CARGO_TARGET_DIR", &target_dir)
+            .env("LLVM_PROFILE_FILE", profile_dir.join("%p-%m.profraw"))
+    };
+
+    // build test binaries and get their paths
+    let output = env(cmd!(
+        "cargo",
+        "test",
+        "--workspace",
+        "--no-run",
+        "--message-format=json"
+    ))
+    .stdout_capture()
+    .stderr_null()
+    .unchecked()
+    .run()?;
+    let objects: Vec<Utf8PathBuf> = Message::parse_stream(&*output.stdout)
+        .flatten()
+        .filter_map(|msg| match msg {
+            Message::CompilerArtifact(artifact) if artifact.profile.test => artifact.executable,
+            _ => None,
+        })
+        .collect();
+    ensure!(
+        !objects.is_empty(),
+        "No test binary is built with coverage."
+    );
+
+    env(cmd!(
+        "cargo",
+        "nextest",
+        "run",
+        "--workspace",
+        "--no-fail-fast"
+    ))
+    .stdout_null()
+    .stderr_null()
+    .unchecked()
+    .run()?;
+
+    let profraws: Vec<_> = std::fs::read_dir(&profile_dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "profraw"))
+        .collect();
+    ensure!(!profraws.is_empty(), "No profraw file is generated.");
+
+    let profdata = target_dir.join("merged.profdata");
+    let status = Command::new(llvm_tool("llvm-profdata"))
+        .args(["merge", "-sparse", "-o", profdata.as_str()])
+        .args(&profraws)
+        .status()?;
+    ensure!(status.success(), "Failed to merge profiles.");
+
+    let export = |format: &str| -> Result<String> {
+        let mut cmd = Command::new(llvm_tool("llvm-cov"));
+        cmd.args([
+            "export",
+            "-format",
+            format,
+            "-instr-profile",
+            profdata.as_str(),
+        ])
+        // only count code in the workspace
+        .args([
+            "-ignore-filename-regex",
+            r"(\.cargo/registry|\.cargo/git|/rustc/)",
+        ]);
+        if format == "text" {
+            cmd.arg("-summary-only");
+        }
+        for (idx, obj) in objects.iter().enumerate() {
+            if idx != 0 {
+                cmd.arg("-object");
+            }
+            cmd.arg(obj);
+        }
+        let output = cmd.output()?;
+        ensure!(
+            output.status.success(),
+            "llvm-cov export failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let summary = export("text")?;
+    let pkgs = pkg_coverage(&summary, &meta.workspace_packages())?;
+    let lcov = export("lcov")?;
+
+    Ok(WorkspaceCoverage { pkgs, lcov })
+}
+
+/// Find the llvm tool from `rustup component add llvm-tools`, or from PATH.
+fn llvm_tool(name: &str) -> String {
+    let sysroot = cmd!("rustc", "--print", "sysroot").read().ok();
+    let verbose = cmd!("rustc", "-vV").read().ok();
+    let host = verbose
+        .as_deref()
+        .and_then(|v| v.lines().find_map(|line| line.strip_prefix("host: ")));
+    if let (Some(sysroot), Some(host)) = (sysroot, host) {
+        let path = Utf8PathBuf::from_iter([sysroot.trim(), "lib", "rustlib", host.trim(), "bin"])
+            .join(name);
+        if path.exists() {
+            return path.into_string();
+        }
+    }
+    name.to_owned()
+}
+
+#[derive(Deserialize)]
+struct Export {
+    data: Vec<ExportData>,
+}
+
+#[derive(Deserialize)]
+struct ExportData {
+    files: Vec<ExportFile>,
+}
+
+#[derive(Deserialize)]
+struct ExportFile {
+    filename: Utf8PathBuf,
+    summary: Coverage,
+}
+
+/// Attribute file summaries to the package containing the file.
+fn pkg_coverage(summary: &str, pkgs: &[&Package]) -> Result<IndexMap<String, Coverage>> {
+    let export: Export = serde_json::from_str(summary)?;
+
+    // the longest package dir wins for nested packages
+    let mut dirs: Vec<_> = pkgs
+        .iter()
+        .filter_map(|pkg| Some((pkg.manifest_path.parent()?, pkg.name.as_str())))
+        .collect();
+    dirs.sort_unstable_by_key(|(dir, _)| std::cmp::Reverse(dir.as_str().len()));
+
+    let mut map = IndexMap::<String, Coverage>::new();
+    for file in export.data.iter().flat_map(|data| &data.files) {
+        if let Some((_, pkg)) = dirs.iter().find(|(dir, _)| file.filename.starts_with(dir)) {
+            map.entry((*pkg).to_owned()).or_default().add(&file.summary);
+        }
+    }
+    Ok(map)
+}
+
+#[test]
+fn attribute_coverage() -> Result<()> {
+    let meta = super::fixture_metadata();
+    let root = &meta.workspace_root;
+    let file = |name: &str, covered: u64| {
+        let counts = serde_json::json!({ "count": 10, "covered": covered, "percent": 0.0 });
+        serde_json::json!({
+            "filename": root.join(name),
+            "summary": { "lines": counts, "functions": counts, "regions": counts }
+        })
+    };
+    let files = [
+        file("src/lib.rs", 5),
+        file("src/main.rs", 10),
+        // the nested member takes its own files
+        file("corelib/src/lib.rs", 1),
+        file("/elsewhere/a.rs", 1),
+    ];
+    let summary = serde_json::json!({
+        "data": [{ "files": files }],
+        "type": "llvm.coverage.json.export"
+    });
+
+    let map = pkg_coverage(&summary.to_string(), &meta.workspace_packages())?;
+    assert_eq!(map.keys().collect::<Vec<_>>(), ["app", "corelib"]);
+    let app = &map["app"];
+    assert_eq!(app.lines.count, 20);
+    assert_eq!(app.lines.covered, 15);
+    assert_eq!(app.lines.percent, 75.0);
+    assert_eq!(map["corelib"].lines.covered, 1);
+    Ok(())
+}
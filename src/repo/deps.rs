@@ -0,0 +1,125 @@
+use cargo_metadata::{DependencyKind, Package, PackageId};
+use plugin::prelude::*;
+
+/// Dependencies of a package from its manifest and the resolved graph.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct Dependencies {
+    /// direct dependencies by kind
+    pub normal: usize,
+    pub dev: usize,
+    pub build: usize,
+    /// distinct packages in the resolved graph, excluding dev-dependencies
+    pub transitive: usize,
+    /// direct dependencies by source
+    pub registry: usize,
+    pub git: usize,
+    pub path: usize,
+    /// crates with more than one version in the resolved graph:
+    /// `Map<name, versions>`
+    pub duplicates: IndexMap<String, Vec<String>>,
+    /// direct dependencies behind features
+    pub optional: Vec<String>,
+    /// direct dependencies only for some targets, e.g. `cfg(unix)`
+    pub target_specific: Vec<String>,
+}
+
+impl Dependencies {
+    pub fn new(pkg: &Package, meta: &Metadata) -> Self {
+        let mut deps = Dependencies::default();
+        for dep in &pkg.dependencies {
+            match dep.kind {
+                DependencyKind::Normal => deps.normal += 1,
+                DependencyKind::Development => deps.dev += 1,
+                DependencyKind::Build => deps.build += 1,
+                _ => (),
+            }
+
+            match dep.source.as_deref() {
+                Some(src) if src.starts_with("git+") => deps.git += 1,
+                Some(_) => deps.registry += 1,
+                None if dep.path.is_some() => deps.path += 1,
+                None => (),
+            }
+
+            let name = dep.rename.as_ref().unwrap_or(&dep.name);
+            if dep.optional && !deps.optional.contains(name) {
+                deps.optional.push(name.clone());
+            }
+            if dep.target.is_some() && !deps.target_specific.contains(name) {
+                deps.target_specific.push(name.clone());
+            }
+        }
+
+        let resolved = dep_packages(pkg, meta);
+        deps.transitive = resolved.len();
+
+        let mut versions = IndexMap::<&str, Vec<String>>::new();
+        for p in &resolved {
+            versions
+                .entry(&p.name)
+                .or_default()
+                .push(p.version.to_string());
+        }
+        deps.duplicates = versions
+            .into_iter()
+            .filter(|(_, v)| v.len() > 1)
+            .map(|(name, mut v)| {
+                v.sort_unstable();
+                (name.to_owned(), v)
+            })
+            .collect();
+        deps.duplicates.sort_unstable_keys();
+
+        deps
+    }
+}
+
+/// Transitive dependencies in the resolved graph, excluding dev-dependencies.
+pub fn dep_packages<'a>(pkg: &Package, meta: &'a Metadata) -> Vec<&'a Package> {
+    let Some(resolve) = &meta.resolve else {
+        return Vec::new();
+    };
+    let node = |id: &PackageId| resolve.nodes.iter().find(|n| n.id == *id);
+
+    let mut visited = vec![&pkg.id];
+    let mut stack = vec![&pkg.id];
+    while let Some(id) = stack.pop() {
+        let Some(node) = node(id) else { continue };
+        for dep in &node.deps {
+            let not_dev = dep
+                .dep_kinds
+                .iter()
+                .any(|k| k.kind != DependencyKind::Development);
+            if not_dev && !visited.contains(&&dep.pkg) {
+                visited.push(&dep.pkg);
+                stack.push(&dep.pkg);
+            }
+        }
+    }
+
+    meta.packages
+        .iter()
+        .filter(|p| p.id != pkg.id && visited.contains(&&p.id))
+        .collect()
+}
+
+#[test]
+fn deps_of_fixture() {
+    let meta = super::fixture_metadata();
+    let pkg = meta.root_package().unwrap();
+    let deps = Dependencies::new(pkg, &meta);
+    let expected = Dependencies {
+        normal: 2,
+        dev: 1,
+        build: 0,
+        // `extra` isn't enabled by default, and `helper` is a dev-dependency
+        transitive: 1,
+        registry: 0,
+        git: 0,
+        path: 3,
+        duplicates: IndexMap::new(),
+        optional: vec!["extra".to_owned()],
+        target_specific: Vec::new(),
+    };
+    assert_eq!(deps, expected);
+}
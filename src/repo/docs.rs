@@ -0,0 +1,229 @@
+use cargo_metadata::{diagnostic::DiagnosticLevel, Message, Package};
+use plugin::prelude::*;
+
+/// `[package.metadata.docs.rs]` in the manifest.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case")]
+struct DocsRsMeta {
+    features: Vec<String>,
+    all_features: bool,
+    no_default_features: bool,
+    default_target: Option<String>,
+    targets: Vec<String>,
+    rustdoc_args: Vec<String>,
+}
+
+impl DocsRsMeta {
+    fn new(pkg: &Package) -> Self {
+        let meta = &pkg.metadata["docs"]["rs"];
+        if meta.is_null() {
+            return Self::default();
+        }
+        serde_json::from_value(meta.clone())
+            .inspect_err(|err| warn!(?err, "Invalid docs.rs metadata"))
+            .unwrap_or_default()
+    }
+
+    /// docs.rs builds the default target, or the first of targets.
+    fn target(&self) -> Option<&str> {
+        self.default_target
+            .as_deref()
+            .or(self.targets.first().map(|t| t.as_str()))
+    }
+
+    fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.all_features {
+            args.push("--all-features".to_owned());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_owned());
+        }
+        if !self.features.is_empty() {
+            args.push(format!("--features={}", self.features.join(",")));
+        }
+        if let Some(target) = self.target() {
+            args.push(format!("--target={target}"));
+        }
+        args
+    }
+
+    /// Flags for `CARGO_ENCODED_RUSTDOCFLAGS`, separated by `\x1f` so that
+    /// an arg may contain spaces.
+    fn rustdocflags(&self) -> String {
+        let mut flags = vec!["--cfg", "docsrs"];
+        flags.extend(self.rustdoc_args.iter().map(|s| s.as_str()));
+        flags.join("\x1f")
+    }
+}
+
+/// A warning from rustdoc, e.g. broken intra-doc links or missing docs.
+#[derive(Debug, Serialize)]
+pub struct DocWarning {
+    /// e.g. rustdoc::broken_intra_doc_links
+    pub lint: Option<String>,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+}
+
+/// Documented public items in the lib, from `--show-coverage`.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct DocCoverage {
+    pub total: u64,
+    pub documented: u64,
+    pub percent: f64,
+}
+
+/// Result of building docs of a package like docs.rs.
+#[derive(Debug, Serialize)]
+pub struct Docs {
+    pub success: bool,
+    /// the target from docs.rs metadata
+    pub target: Option<String>,
+    pub warnings: Vec<DocWarning>,
+    /// warning count by lint
+    pub warning_counts: IndexMap<String, usize>,
+    /// None if the package has no lib or coverage fails
+    pub coverage: Option<DocCoverage>,
+}
+
+impl Docs {
+    /// Run `cargo doc --no-deps` and `--show-coverage` on the package. A
+    /// separate target dir is used to not invalidate the normal build.
+    pub fn new(pkg: &Package, meta: &Metadata) -> Result<Self> {
+        let _span = error_span!("docs", pkg = pkg.name.as_str()).entered();
+
+        let docs_rs = DocsRsMeta::new(pkg);
+        let target_dir = meta.target_directory.join("os-checker-docs");
+        let cargo = |args: Vec<String>| {
+            duct::cmd("cargo", args)
+                .dir(&meta.workspace_root)
+                .env("CARGO_TARGET_DIR", &target_dir)
+                .env("CARGO_ENCODED_RUSTDOCFLAGS", docs_rs.rustdocflags())
+                .stderr_null()
+                .stdout_capture()
+                .unchecked()
+                .run()
+        };
+        let manifest = format!("--manifest-path={}", pkg.manifest_path);
+
+        let mut args: Vec<String> = ["doc", "--no-deps", "--message-format=json", &manifest]
+            .map(String::from)
+            .into();
+        args.extend(docs_rs.cargo_args());
+        let output = cargo(args)?;
+        let warnings = parse_doc_warnings(&output.stdout, pkg);
+        let mut warning_counts = IndexMap::<String, usize>::new();
+        for w in &warnings {
+            let lint = w.lint.as_deref().unwrap_or("other");
+            *warning_counts.entry(lint.to_owned()).or_default() += 1;
+        }
+
+        let coverage = if pkg.targets.iter().any(|t| t.is_lib()) {
+            let mut args: Vec<String> = ["rustdoc", "--lib", &manifest].map(String::from).into();
+            args.extend(docs_rs.cargo_args());
+            args.extend(
+                [
+                    "--",
+                    "-Zunstable-options",
+                    "--show-coverage",
+                    "--output-format=json",
+                ]
+                .map(String::from),
+            );
+            let output = cargo(args)?;
+            std::str::from_utf8(&output.stdout)
+                .map_err(eyre::Report::from)
+                .and_then(parse_doc_coverage)
+                .inspect_err(|err| warn!(?err, "Failed to get doc coverage"))
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(Docs {
+            success: output.status.success(),
+            target: docs_rs.target().map(String::from),
+            warnings,
+            warning_counts,
+            coverage,
+        })
+    }
+}
+
+fn parse_doc_warnings(stdout: &[u8], pkg: &Package) -> Vec<DocWarning> {
+    Message::parse_stream(stdout)
+        .flatten()
+        .filter_map(|msg| match msg {
+            Message::CompilerMessage(msg) if msg.package_id == pkg.id => Some(msg.message),
+            _ => None,
+        })
+        .filter(|diag| diag.level == DiagnosticLevel::Warning && !diag.spans.is_empty())
+        .map(|diag| {
+            let span = diag.spans.iter().find(|s| s.is_primary);
+            DocWarning {
+                lint: diag.code.map(|c| c.code),
+                message: diag.message,
+                file: span.map(|s| s.file_name.clone()),
+                line: span.map(|s| s.line_start),
+            }
+        })
+        .collect()
+}
+
+/// `{"src/lib.rs":{"total":5,"with_docs":3,"total_examples":1,"with_examples":0}}`
+fn parse_doc_coverage(stdout: &str) -> Result<DocCoverage> {
+    #[derive(Deserialize)]
+    struct FileCoverage {
+        total: u64,
+        with_docs: u64,
+    }
+
+    let json = stdout
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .with_context(|| "No json output from --show-coverage.")?;
+    let files: IndexMap<String, FileCoverage> = serde_json::from_str(json)?;
+    let total: u64 = files.values().map(|f| f.total).sum();
+    let documented: u64 = files.values().map(|f| f.with_docs).sum();
+    let percent = if total == 0 {
+        100.0
+    } else {
+        documented as f64 * 100.0 / total as f64
+    };
+    Ok(DocCoverage {
+        total,
+        documented,
+        percent,
+    })
+}
+
+#[test]
+fn docs_rs_metadata() -> Result<()> {
+    let meta = serde_json::json!({
+        "features": ["a", "b"],
+        "no-default-features": true,
+        "targets": ["x86_64-unknown-linux-gnu"],
+        "rustdoc-args": ["--cfg", "feature=\"x y\""]
+    });
+    let docs_rs: DocsRsMeta = serde_json::from_value(meta)?;
+    assert_eq!(
+        docs_rs.cargo_args(),
+        [
+            "--no-default-features",
+            "--features=a,b",
+            "--target=x86_64-unknown-linux-gnu"
+        ]
+    );
+    assert_eq!(
+        docs_rs.rustdocflags(),
+        "--cfg\x1fdocsrs\x1f--cfg\x1ffeature=\"x y\""
+    );
+
+    let stdout = r#"{"src/lib.rs":{"total":4,"with_docs":3,"total_examples":1,"with_examples":0},"src/a.rs":{"total":4,"with_docs":3,"total_examples":0,"with_examples":0}}"#;
+    let cov = parse_doc_coverage(stdout)?;
+    assert_eq!(cov.total, 8);
+    assert_eq!(cov.percent, 75.0);
+    Ok(())
+}
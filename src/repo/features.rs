@@ -0,0 +1,168 @@
+use super::check_errors::check_result;
+use cargo_metadata::Package;
+use plugin::prelude::*;
+
+/// A feature combination passed to `cargo check`.
+#[derive(Debug, Serialize)]
+pub struct FeatureCombo {
+    pub no_default_features: bool,
+    pub all_features: bool,
+    pub features: Vec<String>,
+}
+
+impl FeatureCombo {
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.no_default_features {
+            args.push("--no-default-features".to_owned());
+        }
+        if self.all_features {
+            args.push("--all-features".to_owned());
+        }
+        if !self.features.is_empty() {
+            args.push(format!("--features={}", self.features.join(",")));
+        }
+        args
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureFailure {
+    pub combo: FeatureCombo,
+    /// errors from `cargo check`
+    pub error: String,
+}
+
+/// Result of checking the feature powerset of a package, like cargo-hack.
+#[derive(Debug, Serialize)]
+pub struct FeatureCheck {
+    /// features in the powerset, excluding skipped ones and optional deps
+    pub features: Vec<String>,
+    pub depth: usize,
+    pub checked: usize,
+    pub failed: Vec<FeatureFailure>,
+}
+
+impl FeatureCheck {
+    /// Check default features, all features, and combinations of at most
+    /// `depth` features without default features. A separate target dir is
+    /// used to not invalidate the normal build.
+    pub fn new(pkg: &Package, meta: &Metadata) -> Self {
+        let _span = error_span!("features", pkg = pkg.name.as_str()).entered();
+
+        let depth = crate::config::feature_depth();
+        let features = powerset_features(pkg, &crate::config::feature_skip());
+        let combos = combos(&features, depth);
+        let target_dir = meta.target_directory.join("os-checker-features");
+
+        let checked = combos.len();
+        let failed = combos
+            .into_iter()
+            .filter_map(|combo| {
+                let error = check(pkg, meta, &target_dir, &combo).err()?;
+                Some(FeatureFailure { combo, error })
+            })
+            .collect();
+
+        FeatureCheck {
+            features,
+            depth,
+            checked,
+            failed,
+        }
+    }
+}
+
+/// Features to combine. Implicit features of optional dependencies are
+/// excluded like cargo-hack without `--optional-deps`.
+fn powerset_features(pkg: &Package, skip: &[String]) -> Vec<String> {
+    let mut features: Vec<_> = pkg
+        .features
+        .iter()
+        .filter(|(name, _)| *name != "default" && !skip.contains(name))
+        .filter(|(name, enables)| !matches!(&enables[..], [dep] if *dep == format!("dep:{name}")))
+        .map(|(name, _)| name.clone())
+        .collect();
+    features.sort_unstable();
+    features
+}
+
+fn combos(features: &[String], depth: usize) -> Vec<FeatureCombo> {
+    let mut combos = vec![FeatureCombo {
+        no_default_features: false,
+        all_features: false,
+        features: Vec::new(),
+    }];
+    // subsets in the order of size, e.g. [], [a], [b], [a, b]
+    let mut subsets: Vec<Vec<&String>> = vec![Vec::new()];
+    let mut last: Vec<(usize, Vec<&String>)> = vec![(0, Vec::new())];
+    for _ in 0..depth {
+        let mut next = Vec::new();
+        for (start, subset) in &last {
+            for (idx, feature) in features.iter().enumerate().skip(*start) {
+                let mut subset = subset.clone();
+                subset.push(feature);
+                next.push((idx + 1, subset));
+            }
+        }
+        subsets.extend(next.iter().map(|(_, s)| s.clone()));
+        last = next;
+    }
+    combos.extend(subsets.into_iter().map(|subset| FeatureCombo {
+        no_default_features: true,
+        all_features: false,
+        features: subset.into_iter().cloned().collect(),
+    }));
+    if !features.is_empty() {
+        combos.push(FeatureCombo {
+            no_default_features: false,
+            all_features: true,
+            features: Vec::new(),
+        });
+    }
+    combos
+}
+
+fn check(
+    pkg: &Package,
+    meta: &Metadata,
+    target_dir: &Utf8Path,
+    combo: &FeatureCombo,
+) -> Result<(), String> {
+    let mut args = vec![
+        "check".to_owned(),
+        "--manifest-path".to_owned(),
+        pkg.manifest_path.to_string(),
+    ];
+    args.extend(combo.args());
+    let output = duct::cmd("cargo", args)
+        .dir(&meta.workspace_root)
+        .env("CARGO_TARGET_DIR", target_dir)
+        .stdout_null()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(|err| err.to_string())?;
+    check_result(&output)
+}
+
+#[test]
+fn feature_powerset() -> Result<()> {
+    let features: Vec<_> = ["a", "b", "c"].map(String::from).into();
+    let combos = combos(&features, 2);
+    let args: Vec<_> = combos.iter().map(|c| c.args().join(" ")).collect();
+    // default, no default, 3 singles, 3 pairs, all
+    assert_eq!(combos.len(), 9);
+    assert_eq!(args[0], "");
+    assert_eq!(args[1], "--no-default-features");
+    assert_eq!(args[2], "--no-default-features --features=a");
+    assert_eq!(args[7], "--no-default-features --features=b,c");
+    assert_eq!(args[8], "--all-features");
+
+    // `default` and `extra = ["dep:extra"]` are not in the powerset
+    let meta = super::fixture_metadata();
+    let pkg = meta.root_package().unwrap();
+    assert_eq!(powerset_features(pkg, &[]), ["a", "b", "c"]);
+    assert_eq!(powerset_features(pkg, &["b".to_owned()]), ["a", "c"]);
+    Ok(())
+}
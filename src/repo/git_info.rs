@@ -1,50 +1,434 @@
-use plugin::prelude::{cmd, duct, Result, Timestamp, Utf8Path};
+use gix::{reference::Category, refs::TargetRef, revision::walk::Sorting};
+use plugin::prelude::{Timestamp, Utf8Path, Utf8PathBuf};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, thiserror::Error)]
+pub enum GitError {
+    #[error("Failed to open the git repository in {0}")]
+    Open(Utf8PathBuf, #[source] Box<gix::discover::Error>),
+    #[error("{path} is not in the git repository at {root}")]
+    OutsideRepo {
+        path: Utf8PathBuf,
+        root: Utf8PathBuf,
+    },
+    #[error("The git repository has no work tree")]
+    Bare,
+    #[error("The work tree {0:?} is not a UTF-8 path")]
+    NonUtf8Path(std::path::PathBuf),
+    #[error("Failed to find the commit of HEAD")]
+    HeadCommit(#[from] gix::reference::head_commit::Error),
+    #[error("Failed to find a reference")]
+    Reference(#[from] gix::reference::find::existing::Error),
+    #[error("Failed to iterate references")]
+    References(#[from] gix::reference::iter::Error),
+    #[error("Failed to iterate references")]
+    ReferencesInit(#[from] gix::reference::iter::init::Error),
+    #[error("Failed to read a reference")]
+    ReadReference(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to peel a reference")]
+    Peel(#[from] gix::reference::peel::Error),
+    #[error("Failed to start walking commits")]
+    Walk(#[from] gix::revision::walk::Error),
+    #[error("Failed to walk commits")]
+    WalkIter(#[from] gix::revision::walk::iter::Error),
+    #[error("Failed to read an object")]
+    Object(#[from] gix::object::find::existing::Error),
+    #[error("Failed to find a commit")]
+    FindCommit(#[from] gix::object::find::existing::with_conversion::Error),
+    #[error("Failed to decode a commit")]
+    Decode(#[from] gix::objs::decode::Error),
+    #[error("Failed to read the tree of a commit")]
+    Tree(#[from] gix::object::commit::Error),
+    #[error("Invalid commit time {0}")]
+    Time(i64),
+}
+
+/// A git repository opened in process.
+#[derive(Debug)]
+pub struct GitRepo {
+    repo: gix::Repository,
+    /// root of the work tree
+    root: Utf8PathBuf,
+}
+
+/// A commit and its author.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub sha: String,
+    pub time: Timestamp,
+    pub author_email: String,
+}
+
+/// A tag and the commit it points to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub commit: Option<String>,
+}
+
+impl GitRepo {
+    /// Open the repository containing `dir`.
+    pub fn open(dir: &Utf8Path) -> Result<Self, GitError> {
+        let repo = gix::discover(dir).map_err(|err| GitError::Open(dir.to_owned(), err.into()))?;
+        let root = repo.workdir().ok_or(GitError::Bare)?;
+        let root = Utf8PathBuf::from_path_buf(root.canonicalize().unwrap_or(root.to_owned()))
+            .map_err(GitError::NonUtf8Path)?;
+        Ok(GitRepo { repo, root })
+    }
+
+    pub fn head_commit(&self) -> Result<Commit, GitError> {
+        let commit = self.repo.head_commit()?;
+        to_commit(&commit)
+    }
+
+    /// The branch HEAD is on, or None if HEAD is detached.
+    pub fn current_branch(&self) -> Result<Option<String>, GitError> {
+        let name = self.repo.head_name()?;
+        Ok(name.map(|name| name.shorten().to_string()))
+    }
+
+    /// The branch `refs/remotes/<remote>/HEAD` points to, preferring
+    /// `origin`, e.g. `main` for `refs/remotes/origin/main`.
+    pub fn remote_default_branch(&self) -> Result<Option<String>, GitError> {
+        let refs = self.repo.references()?;
+        let mut heads = Vec::new();
+        for reference in refs.prefixed("refs/remotes/")? {
+            let reference = reference.map_err(GitError::ReadReference)?;
+            let Some((Category::RemoteBranch, name)) = reference.name().category_and_short_name()
+            else {
+                continue;
+            };
+            let name = name.to_string();
+            let Some(remote) = name.strip_suffix("/HEAD") else {
+                continue;
+            };
+            if let TargetRef::Symbolic(target) = reference.target() {
+                let target = target.shorten().to_string();
+                if let Some(branch) = target.strip_prefix(&format!("{remote}/")) {
+                    heads.push((remote != "origin", branch.to_owned()));
+                }
+            }
+        }
+        heads.sort_unstable();
+        Ok(heads.into_iter().next().map(|(_, branch)| branch))
+    }
+
+    /// Tags and the commits they point to. Annotated tags are peeled.
+    pub fn tags(&self) -> Result<Vec<Tag>, GitError> {
+        let refs = self.repo.references()?;
+        let mut tags = Vec::new();
+        for reference in refs.tags()? {
+            let mut reference = reference.map_err(GitError::ReadReference)?;
+            let name = reference.name().shorten().to_string();
+            // tags of trees or blobs have no commit
+            let id = reference.peel_to_id()?;
+            let object = self.repo.find_header(id)?;
+            let commit = (object.kind() == gix::object::Kind::Commit).then(|| id.to_string());
+            tags.push(Tag { name, commit });
+        }
+        Ok(tags)
+    }
+
+    /// Commits reachable from HEAD, from the newest.
+    pub fn commits(&self) -> Result<impl Iterator<Item = Result<Commit, GitError>> + '_, GitError> {
+        let head = self.repo.head_commit()?.id;
+        let walk = self
+            .repo
+            .rev_walk([head])
+            .sorting(Sorting::ByCommitTime(Default::default()))
+            .all()?;
+        Ok(walk.map(|info| to_commit(&info?.object()?)))
+    }
+
+    /// Commits reachable from HEAD touching each of `dirs`, from the newest,
+    /// in one walk of the history. A change is counted for the deepest dir
+    /// containing it, so nested dirs, e.g. nested packages, are excluded
+    /// from their parent dirs. A commit touches a dir if the dir differs
+    /// from each of its parents, including deleting the dir.
+    ///
+    /// Unlike `git log -- <dir>`, history is not simplified: a merge equal
+    /// to one of its parents is skipped, but commits of all its parents are
+    /// still walked.
+    pub fn commits_touching(&self, dirs: &[&Utf8Path]) -> Result<Vec<Vec<Commit>>, GitError> {
+        let dirs = dirs
+            .iter()
+            .map(|dir| self.relative(dir))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let head = self.repo.head_commit()?.id;
+        let walk = self
+            .repo
+            .rev_walk([head])
+            .sorting(Sorting::ByCommitTime(Default::default()))
+            .all()?;
+        // tree of each commit
+        let mut trees = HashMap::new();
+        let mut tree_id = |id: gix::ObjectId| -> Result<gix::ObjectId, GitError> {
+            if let Some(tree) = trees.get(&id) {
+                return Ok(*tree);
+            }
+            let tree = self.repo.find_commit(id)?.tree_id()?.detach();
+            trees.insert(id, tree);
+            Ok(tree)
+        };
+
+        let mut commits = vec![Vec::new(); dirs.len()];
+        for info in walk {
+            let info = info?;
+            let tree = tree_id(info.id)?;
+            // a root commit is compared with an empty tree
+            let mut parents = vec![];
+            for parent in info.parent_ids() {
+                parents.push(Some(tree_id(parent.detach())?));
+            }
+            if parents.is_empty() {
+                parents.push(None);
+            }
+            // dirs changed compared with every parent
+            let mut touched = vec![true; dirs.len()];
+            for parent in parents {
+                let mut changed = vec![false; dirs.len()];
+                if parent != Some(tree) {
+                    self.diff_dirs(parent, Some(tree), "".into(), &dirs, &mut changed)?;
+                }
+                for (touched, changed) in touched.iter_mut().zip(changed) {
+                    *touched &= changed;
+                }
+            }
+            if touched.contains(&true) {
+                let commit = to_commit(&info.object()?)?;
+                for (commits, _) in commits.iter_mut().zip(&touched).filter(|(_, t)| **t) {
+                    commits.push(commit.clone());
+                }
+            }
+        }
+        Ok(commits)
+    }
+
+    /// The path relative to the root of the work tree.
+    fn relative(&self, path: &Utf8Path) -> Result<Utf8PathBuf, GitError> {
+        let path = path.canonicalize_utf8().unwrap_or(path.to_owned());
+        match path.strip_prefix(&self.root) {
+            Ok(rel) => Ok(rel.to_owned()),
+            Err(_) => Err(GitError::OutsideRepo {
+                path,
+                root: self.root.clone(),
+            }),
+        }
+    }
+
+    /// Mark `dirs` changed between two different trees at `path`; None is
+    /// an absent tree. Only subtrees containing deeper dirs are compared
+    /// entry by entry.
+    fn diff_dirs(
+        &self,
+        old: Option<gix::ObjectId>,
+        new: Option<gix::ObjectId>,
+        path: &Utf8Path,
+        dirs: &[Utf8PathBuf],
+        changed: &mut [bool],
+    ) -> Result<(), GitError> {
+        if !dirs.iter().any(|dir| dir.starts_with(path) && dir != path) {
+            mark_deepest(path, dirs, changed);
+            return Ok(());
+        }
+        // `[old, new]` of each entry: the object id and whether it's a tree
+        let mut entries = BTreeMap::<String, [Option<(gix::ObjectId, bool)>; 2]>::new();
+        for (side, tree) in [old, new].into_iter().enumerate() {
+            let Some(tree) = tree else { continue };
+            for entry in self.repo.find_tree(tree)?.iter() {
+                let entry = entry?;
+                let id = (entry.id().detach(), entry.mode().is_tree());
+                entries.entry(entry.filename().to_string()).or_default()[side] = Some(id);
+            }
+        }
+        let tree = |entry: Option<(gix::ObjectId, bool)>| entry.filter(|e| e.1).map(|e| e.0);
+        let is_file = |entry: Option<(gix::ObjectId, bool)>| entry.is_some_and(|e| !e.1);
+        for (name, [old, new]) in entries {
+            if old == new {
+                continue;
+            }
+            let path = path.join(name);
+            if tree(old) != tree(new) {
+                self.diff_dirs(tree(old), tree(new), &path, dirs, changed)?;
+            }
+            // a file is added, removed or modified
+            if is_file(old) || is_file(new) {
+                mark_deepest(&path, dirs, changed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mark the deepest dirs containing `path` changed.
+fn mark_deepest(path: &Utf8Path, dirs: &[Utf8PathBuf], changed: &mut [bool]) {
+    let depth = dirs
+        .iter()
+        .filter(|dir| path.starts_with(dir))
+        .map(|dir| dir.components().count())
+        .max();
+    for (dir, changed) in dirs.iter().zip(changed) {
+        if path.starts_with(dir) && Some(dir.components().count()) == depth {
+            *changed = true;
+        }
+    }
+}
+
+fn to_commit(commit: &gix::Commit) -> Result<Commit, GitError> {
+    let seconds = commit.time()?.seconds;
+    Ok(Commit {
+        sha: commit.id.to_string(),
+        time: Timestamp::from_second(seconds).map_err(|_| GitError::Time(seconds))?,
+        author_email: commit.author()?.email.to_string(),
+    })
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GitInfo {
     pub last_commit: Timestamp,
     pub sha: String,
+    /// the current branch, or the default branch of the remote if HEAD is
+    /// detached
     pub branch: String,
+    pub detached: bool,
 }
 
 impl GitInfo {
-    pub fn new(root: &Utf8Path) -> Result<Self> {
-        let last_commit = last_commit_time(root)?;
-        let sha = head_sha(root)?;
-        let branch = current_branch(root)?;
+    pub fn new(repo: &GitRepo) -> Result<Self, GitError> {
+        let head = repo.head_commit()?;
+        let (branch, detached) = match repo.current_branch()? {
+            Some(branch) => (branch, false),
+            None => {
+                let branch = repo.remote_default_branch()?;
+                if branch.is_none() {
+                    warn!(root = %repo.root, "HEAD is detached and the remote default branch is unknown");
+                }
+                (branch.unwrap_or_default(), true)
+            }
+        };
         Ok(Self {
-            last_commit,
-            sha,
+            last_commit: head.time,
+            sha: head.sha,
             branch,
+            detached,
         })
     }
 }
 
-fn run(expr: duct::Expression, root: &Utf8Path) -> Result<String> {
-    Ok(expr.dir(root).read()?.trim().to_owned())
+/// Git history of a package, i.e. commits touching files in its directory.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PkgGitStats {
+    pub last_commit: Timestamp,
+    pub last_commit_sha: String,
+    pub first_commit: Timestamp,
+    pub commits: usize,
+    pub commits_30d: usize,
+    pub commits_90d: usize,
+    pub commits_365d: usize,
+    /// distinct author emails
+    pub authors: usize,
 }
 
-fn last_commit_time(root: &Utf8Path) -> Result<Timestamp> {
-    let cmd = cmd!("git", "log", "-1", "--pretty=format:%ct");
-    // git log -1 --format="%ct"
-    let unix_timestamp = run(cmd, root)?.parse()?;
-    Ok(Timestamp::from_second(unix_timestamp)?)
-}
+impl PkgGitStats {
+    /// Stats of each package dir in one walk of the history; a package
+    /// nested in another package dir is excluded from it. None if no commit
+    /// touches the package dir, e.g. it's untracked.
+    pub fn new(repo: &GitRepo, pkg_dirs: &[&Utf8Path]) -> Result<Vec<Option<Self>>, GitError> {
+        let now = Timestamp::now();
+        let commits = repo.commits_touching(pkg_dirs)?;
+        Ok(commits
+            .iter()
+            .map(|commits| Self::from_commits(commits, now))
+            .collect())
+    }
 
-fn head_sha(root: &Utf8Path) -> Result<String> {
-    // git rev-parse HEAD
-    run(cmd!("git", "rev-parse", "HEAD"), root)
+    /// `commits` are ordered from the newest.
+    fn from_commits(commits: &[Commit], now: Timestamp) -> Option<Self> {
+        let last = commits.first()?;
+        let since = |days: i64| {
+            let secs = now.as_second() - days * 24 * 3600;
+            commits
+                .iter()
+                .filter(|c| c.time.as_second() >= secs)
+                .count()
+        };
+        let mut authors: Vec<_> = commits
+            .iter()
+            .map(|c| c.author_email.to_lowercase())
+            .collect();
+        authors.sort_unstable();
+        authors.dedup();
+        Some(Self {
+            last_commit: commits.iter().map(|c| c.time).max().unwrap_or(last.time),
+            last_commit_sha: last.sha.clone(),
+            first_commit: commits.iter().map(|c| c.time).min().unwrap_or(last.time),
+            commits: commits.len(),
+            commits_30d: since(30),
+            commits_90d: since(90),
+            commits_365d: since(365),
+            authors: authors.len(),
+        })
+    }
 }
 
-fn current_branch(root: &Utf8Path) -> Result<String> {
-    // git branch --show-current
-    run(cmd!("git", "branch", "--show-current"), root)
+#[test]
+fn git_info() -> Result<(), GitError> {
+    let repo = GitRepo::open(".".into())?;
+    dbg!(GitInfo::new(&repo)?);
+
+    let head = repo.head_commit()?;
+    assert_eq!(repo.commits()?.next().transpose()?, Some(head));
+    dbg!(repo.remote_default_branch()?);
+    dbg!(repo.tags()?);
+    Ok(())
 }
 
 #[test]
-fn git_info() -> Result<()> {
-    let root = ".".into();
-    dbg!(GitInfo::new(root)?);
+fn pkg_git_stats() -> Result<(), GitError> {
+    let now = Timestamp::from_second(100 * 24 * 3600).unwrap();
+    let commit = |sha: &str, day: i64, email: &str| Commit {
+        sha: sha.to_owned(),
+        time: Timestamp::from_second(day * 24 * 3600).unwrap(),
+        author_email: email.to_owned(),
+    };
+    let commits = [
+        commit("c3", 95, "a@x.com"),
+        commit("c2", 20, "B@x.com"),
+        commit("c1", 1, "b@x.com"),
+    ];
+    let stats = PkgGitStats::from_commits(&commits, now).unwrap();
+    assert_eq!(stats.last_commit_sha, "c3");
+    assert_eq!(stats.first_commit.as_second(), 24 * 3600);
+    assert_eq!(
+        (stats.commits, stats.commits_30d, stats.commits_90d),
+        (3, 1, 2)
+    );
+    assert_eq!(stats.authors, 2);
+    assert_eq!(PkgGitStats::from_commits(&[], now), None);
+
+    let repo = GitRepo::open(".".into())?;
+    let [src] = &repo.commits_touching(&["src".into()])?[..] else {
+        unreachable!()
+    };
+    assert!(!src.is_empty() && src.len() <= repo.commits()?.count());
+    // a nested dir only takes commits that touch nothing else
+    let [outside_repo, only_repo] = &repo.commits_touching(&["src".into(), "src/repo".into()])?[..]
+    else {
+        unreachable!()
+    };
+    assert!(outside_repo.iter().all(|c| src.contains(c)));
+    assert!(src.len() <= outside_repo.len() + only_repo.len());
+    assert!(src
+        .iter()
+        .all(|c| outside_repo.contains(c) || only_repo.contains(c)));
+    assert_eq!(only_repo.len(), {
+        let [only] = &repo.commits_touching(&["src/repo".into()])?[..] else {
+            unreachable!()
+        };
+        only.len()
+    });
+    dbg!(PkgGitStats::from_commits(src, Timestamp::now()));
     Ok(())
 }
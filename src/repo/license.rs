@@ -0,0 +1,274 @@
+use super::deps::dep_packages;
+use cargo_metadata::Package;
+use plugin::prelude::*;
+
+/// License of a package and its dependencies.
+#[derive(Debug, Serialize)]
+pub struct License {
+    /// SPDX expression in the `license` field
+    pub expression: Option<String>,
+    /// the `license-file` field
+    pub license_file: Option<String>,
+    /// LICENSE*, LICENCE* and COPYING* files in the package dir
+    pub files: Vec<LicenseFile>,
+    pub file_exists: bool,
+    /// whether licenses recognized from the files all appear in the
+    /// expression; None if there is no expression or no recognized file
+    pub manifest_agrees: Option<bool>,
+    pub deps: DepLicenses,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LicenseFile {
+    pub name: String,
+    /// SPDX id recognized from the text
+    pub detected: Option<&'static str>,
+}
+
+impl License {
+    pub fn new(pkg: &Package, meta: &Metadata) -> Self {
+        let files = license_files(pkg);
+        let dir = pkg.manifest_path.parent().unwrap_or(Utf8Path::new(""));
+        let file_exists = !files.is_empty()
+            || pkg
+                .license_file
+                .as_ref()
+                .is_some_and(|path| dir.join(path).exists());
+
+        let detected: Vec<_> = files.iter().filter_map(|f| f.detected).collect();
+        let manifest_agrees = match &pkg.license {
+            Some(expr) if !detected.is_empty() => {
+                let ids: Vec<_> = license_ids(expr).map(normalize_id).collect();
+                Some(detected.iter().all(|id| ids.contains(&normalize_id(id))))
+            }
+            _ => None,
+        };
+
+        License {
+            expression: pkg.license.clone(),
+            license_file: pkg.license_file.as_ref().map(|p| p.to_string()),
+            files,
+            file_exists,
+            manifest_agrees,
+            deps: DepLicenses::new(pkg, meta),
+        }
+    }
+}
+
+fn license_files(pkg: &Package) -> Vec<LicenseFile> {
+    let Some(dir) = pkg.manifest_path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = dir.read_dir_utf8() else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|e| {
+            let name = e.file_name().to_uppercase();
+            ["LICENSE", "LICENCE", "COPYING"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .map(|e| LicenseFile {
+            name: e.file_name().to_owned(),
+            detected: std::fs::read_to_string(e.path())
+                .ok()
+                .and_then(|text| detect_license(&text)),
+        })
+        .collect();
+    files.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    files
+}
+
+/// Recognize common licenses by their headings.
+fn detect_license(text: &str) -> Option<&'static str> {
+    let head: String = text.chars().take(2000).collect();
+    let has = |pat: &str| head.contains(pat);
+    Some(if has("Apache License") && has("Version 2.0") {
+        "Apache-2.0"
+    } else if has("Permission is hereby granted, free of charge") {
+        "MIT"
+    } else if has("GNU LESSER GENERAL PUBLIC LICENSE") {
+        if has("Version 3") {
+            "LGPL-3.0"
+        } else {
+            "LGPL-2.1"
+        }
+    } else if has("GNU AFFERO GENERAL PUBLIC LICENSE") {
+        "AGPL-3.0"
+    } else if has("GNU GENERAL PUBLIC LICENSE") {
+        if has("Version 3") {
+            "GPL-3.0"
+        } else {
+            "GPL-2.0"
+        }
+    } else if has("Mozilla Public License Version 2.0") {
+        "MPL-2.0"
+    } else if has("Mulan PubL v2") || has("Mulan Public License") {
+        "MulanPubL-2.0"
+    } else if has("Mulan PSL v2") || has("Mulan Permissive Software License") {
+        "MulanPSL-2.0"
+    } else if has("Boost Software License") {
+        "BSL-1.0"
+    } else if has("This is free and unencumbered software released into the public domain") {
+        "Unlicense"
+    } else if has("Redistribution and use in source and binary forms") {
+        if has("Neither the name") {
+            "BSD-3-Clause"
+        } else {
+            "BSD-2-Clause"
+        }
+    } else if has("Permission to use, copy, modify, and/or distribute this software") {
+        "ISC"
+    } else {
+        return None;
+    })
+}
+
+/// `GPL-3.0-only`, `GPL-3.0-or-later`, `GPL-3.0+` and `GPL-3.0` are the
+/// same for comparison.
+fn normalize_id(id: &str) -> &str {
+    id.trim_end_matches('+')
+        .trim_end_matches("-only")
+        .trim_end_matches("-or-later")
+}
+
+/// License ids in an expression, excluding operators and exceptions.
+fn license_ids(expr: &str) -> impl Iterator<Item = &str> {
+    let mut exception = false;
+    expr.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+        .filter(|s| !s.is_empty())
+        .filter(move |s| {
+            let skip = exception || matches!(*s, "AND" | "OR" | "WITH");
+            exception = *s == "WITH";
+            !skip
+        })
+}
+
+/// An SPDX expression in disjunctive normal form: any of the alternatives,
+/// each of which requires all the licenses. The legacy `/` means OR.
+fn dnf(expr: &str) -> Vec<Vec<String>> {
+    let spaced = expr
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .replace('/', " OR ");
+    let tokens: Vec<_> = spaced.split_whitespace().collect();
+    let mut pos = 0;
+    or_expr(&tokens, &mut pos)
+}
+
+fn or_expr(tokens: &[&str], pos: &mut usize) -> Vec<Vec<String>> {
+    let mut alts = and_expr(tokens, pos);
+    while tokens.get(*pos) == Some(&"OR") {
+        *pos += 1;
+        alts.extend(and_expr(tokens, pos));
+    }
+    alts
+}
+
+fn and_expr(tokens: &[&str], pos: &mut usize) -> Vec<Vec<String>> {
+    let mut alts = atom(tokens, pos);
+    while tokens.get(*pos) == Some(&"AND") {
+        *pos += 1;
+        let rhs = atom(tokens, pos);
+        alts = alts
+            .iter()
+            .flat_map(|a| {
+                rhs.iter()
+                    .map(move |b| a.iter().chain(b).cloned().collect())
+            })
+            .collect();
+    }
+    alts
+}
+
+fn atom(tokens: &[&str], pos: &mut usize) -> Vec<Vec<String>> {
+    match tokens.get(*pos) {
+        Some(&"(") => {
+            *pos += 1;
+            let alts = or_expr(tokens, pos);
+            if tokens.get(*pos) == Some(&")") {
+                *pos += 1;
+            }
+            alts
+        }
+        Some(id) => {
+            *pos += 1;
+            // the exception doesn't change the license
+            if tokens.get(*pos) == Some(&"WITH") {
+                *pos += 2;
+            }
+            vec![vec![id.to_string()]]
+        }
+        None => vec![vec![]],
+    }
+}
+
+/// Licenses of transitive normal and build dependencies.
+#[derive(Debug, Default, Serialize)]
+pub struct DepLicenses {
+    /// dependency count by license expression
+    pub licenses: IndexMap<String, usize>,
+    /// `name@version` without `license` field
+    pub unknown: Vec<String>,
+    /// `name@version` whose license can't avoid the deny list
+    pub denied: Vec<String>,
+    /// `name@version` whose license can't be satisfied by the allow list;
+    /// empty if no allow list is given
+    pub not_allowed: Vec<String>,
+}
+
+impl DepLicenses {
+    fn new(pkg: &Package, meta: &Metadata) -> Self {
+        let allow = crate::config::license_allow();
+        let deny = crate::config::license_deny();
+        let mut deps = DepLicenses::default();
+        for dep in dep_packages(pkg, meta) {
+            let id = format!("{}@{}", dep.name, dep.version);
+            let Some(expr) = &dep.license else {
+                deps.unknown.push(id);
+                continue;
+            };
+            *deps.licenses.entry(expr.clone()).or_default() += 1;
+
+            let alts = dnf(expr);
+            let listed = |list: &[String], license: &String| {
+                list.iter()
+                    .any(|l| normalize_id(l) == normalize_id(license))
+            };
+            if !deny.is_empty() && alts.iter().all(|alt| alt.iter().any(|l| listed(&deny, l))) {
+                deps.denied.push(id.clone());
+            }
+            if !allow.is_empty() && !alts.iter().any(|alt| alt.iter().all(|l| listed(&allow, l))) {
+                deps.not_allowed.push(id);
+            }
+        }
+        deps.licenses.sort_unstable_by(|_, a, _, b| b.cmp(a));
+        deps
+    }
+}
+
+#[test]
+fn spdx_expressions() {
+    assert_eq!(dnf("MIT OR Apache-2.0"), [["MIT"], ["Apache-2.0"]]);
+    assert_eq!(dnf("MIT/Apache-2.0"), [["MIT"], ["Apache-2.0"]]);
+    assert_eq!(
+        dnf("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+        [
+            ["MIT", "Unicode-DFS-2016"],
+            ["Apache-2.0", "Unicode-DFS-2016"]
+        ]
+    );
+    assert_eq!(
+        dnf("Apache-2.0 WITH LLVM-exception OR MIT"),
+        [["Apache-2.0"], ["MIT"]]
+    );
+    let ids: Vec<_> = license_ids("GPL-3.0-or-later WITH Classpath-exception-2.0 OR MIT").collect();
+    assert_eq!(ids, ["GPL-3.0-or-later", "MIT"]);
+    assert_eq!(normalize_id("GPL-3.0-only"), "GPL-3.0");
+
+    let gpl = std::fs::read_to_string("LICENSE").unwrap();
+    assert_eq!(detect_license(&gpl), Some("GPL-3.0"));
+}
@@ -1,22 +1,179 @@
 use child_wait_timeout::ChildWT;
 use eyre::Result;
 use os_checker_types::Utf8Path;
+use serde::Serialize;
 use std::io::Read;
 use std::process::{Command, Stdio};
 use std::time::Duration;
 
+/// Why a test can't run under Miri.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnsupportedCause {
+    /// calling a foreign function Miri doesn't know
+    ForeignFunction,
+    /// file I/O or other host access under isolation
+    Isolation,
+    InlineAsm,
+    Other,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct MiriUnsupported {
+    pub cause: UnsupportedCause,
+    /// the blocking operation, e.g. the foreign function name
+    pub operation: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MiriStatus {
+    Pass,
+    /// UB or any other error
+    Fail,
+    Timeout,
+    MiriUnsupported,
+}
+
+/// Miri result of a testcase.
+#[derive(Debug)]
+pub struct MiriResult {
+    pub output: Option<String>,
+    pub pass: bool,
+    pub timeout: bool,
+    pub status: MiriStatus,
+    pub unsupported: Option<MiriUnsupported>,
+    /// the test is rerun with `-Zmiri-disable-isolation`
+    pub isolation_disabled: bool,
+}
+
+/// Run a test under Miri and classify the failure. If the test is blocked by
+/// isolation and retrying is enabled, rerun it with isolation disabled so
+/// the result reflects real UB.
+pub fn run_miri(
+    pkg: &str,
+    kind: &str,
+    bin: &str,
+    name: &str,
+    workspace_root: &Utf8Path,
+) -> MiriResult {
+    let (mut output, mut pass, mut timeout) =
+        cargo_miri(pkg, kind, bin, name, workspace_root, None);
+    let mut unsupported = output.as_deref().and_then(classify_unsupported);
+
+    let mut isolation_disabled = false;
+    if crate::config::miri_disable_isolation_retry()
+        && unsupported.as_ref().map(|u| &u.cause) == Some(&UnsupportedCause::Isolation)
+    {
+        isolation_disabled = true;
+        (output, pass, timeout) = cargo_miri(
+            pkg,
+            kind,
+            bin,
+            name,
+            workspace_root,
+            Some("-Zmiri-disable-isolation"),
+        );
+        unsupported = output.as_deref().and_then(classify_unsupported);
+    }
+
+    let status = match (pass, timeout, &unsupported) {
+        (true, _, _) => MiriStatus::Pass,
+        (_, true, _) => MiriStatus::Timeout,
+        (_, _, Some(_)) => MiriStatus::MiriUnsupported,
+        _ => MiriStatus::Fail,
+    };
+    MiriResult {
+        output,
+        pass,
+        timeout,
+        status,
+        unsupported,
+        isolation_disabled,
+    }
+}
+
+/// Recognize errors due to Miri's limitations rather than UB, like
+///
+/// ```text
+/// error: unsupported operation: can't call foreign function `foo` on OS `linux`
+/// error: unsupported operation: `open` not available when isolation is enabled
+/// error: unsupported operation: inline assembly is not supported
+/// ```
+pub fn classify_unsupported(stderr: &str) -> Option<MiriUnsupported> {
+    const PREFIX: &str = "error: unsupported operation: ";
+    let msg = stderr
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(PREFIX))?;
+
+    // the first `quoted` word in the message
+    let quoted = || {
+        let (_, rest) = msg.split_once('`')?;
+        let (word, _) = rest.split_once('`')?;
+        Some(word.to_owned())
+    };
+
+    let (cause, operation) = if let Some(rest) = msg.strip_prefix("can't call foreign function") {
+        // older Miri: can't call foreign function: foo
+        let old = rest.trim_start_matches(':').split_whitespace().next();
+        let operation = quoted().or(old.map(str::to_owned));
+        (UnsupportedCause::ForeignFunction, operation)
+    } else if msg.contains("isolation") {
+        (UnsupportedCause::Isolation, quoted())
+    } else if msg.contains("inline assembly") {
+        (UnsupportedCause::InlineAsm, Some("asm!".to_owned()))
+    } else {
+        (UnsupportedCause::Other, quoted())
+    };
+
+    Some(MiriUnsupported {
+        cause,
+        operation: operation.unwrap_or_else(|| msg.to_owned()),
+    })
+}
+
+#[test]
+fn miri_unsupported() {
+    let ffi = "error: unsupported operation: can't call foreign function `ffi_add` on OS `linux`";
+    let got = classify_unsupported(ffi).unwrap();
+    assert_eq!(got.cause, UnsupportedCause::ForeignFunction);
+    assert_eq!(got.operation, "ffi_add");
+
+    let old_ffi = "error: unsupported operation: can't call foreign function: ffi_add";
+    assert_eq!(classify_unsupported(old_ffi).unwrap().operation, "ffi_add");
+
+    let io = "error: unsupported operation: `open` not available when isolation is enabled\n\
+              note: pass the flag `-Zmiri-disable-isolation` to disable isolation;";
+    let got = classify_unsupported(io).unwrap();
+    assert_eq!(got.cause, UnsupportedCause::Isolation);
+    assert_eq!(got.operation, "open");
+
+    let ub = "error: Undefined Behavior: memory access failed: null pointer is a dangling pointer";
+    assert!(classify_unsupported(ub).is_none());
+}
+
 pub fn cargo_miri(
     pkg: &str,
     kind: &str,
     bin: &str,
     name: &str,
     workspace_root: &Utf8Path,
+    extra_flags: Option<&str>,
 ) -> (Option<String>, bool, bool) {
     let kind = format!("--{kind}");
     let cmd = format!("cargo miri test -p {pkg} {kind} {bin} -- {name}");
-    let _span = error_span!("miri", cmd).entered();
+    let _span = error_span!("miri", cmd, ?extra_flags).entered();
 
-    let Ok(mut child) = Command::new("cargo")
+    let mut command = Command::new("cargo");
+    if let Some(flags) = extra_flags {
+        // keep flags from the environment
+        let miriflags = match std::env::var("MIRIFLAGS") {
+            Ok(env) => format!("{env} {flags}"),
+            Err(_) => flags.to_owned(),
+        };
+        command.env("MIRIFLAGS", miriflags);
+    }
+    let Ok(mut child) = command
         .args(["miri", "test", "-p", pkg, &kind, bin, "--", name])
         .stderr(Stdio::piped())
         .current_dir(workspace_root)
@@ -75,6 +232,7 @@ fn miri_output() {
         "t1",
         "miri_should_err",
         ".".into(),
+        None,
     )
     .0
     .unwrap();
@@ -1,18 +1,37 @@
 use crate::{crates_io::IndexFile, database::diag_total_count};
+use build::{BuildStatus, BuildTime};
 use cargo_metadata::Package;
+use coverage::Coverage;
 use eyre::ContextCompat;
 use output::Output;
 use plugin::{prelude::*, write_json};
 use std::sync::LazyLock;
-use testcases::PkgTests;
+use testcases::{PkgTests, TestsWarning};
 
 mod git_info;
-pub use git_info::GitInfo;
-
+pub use git_info::{GitError, GitInfo, GitRepo, PkgGitStats};
+
+mod advisory;
+mod build;
+mod check_errors;
+mod coverage;
+mod deps;
+mod docs;
+mod features;
+mod license;
 mod miri;
+mod msrv;
 mod os_checker;
+mod outdated;
 mod output;
+mod release_tags;
+mod sanitizer;
+pub use sanitizer::Sanitizer;
+mod semver_check;
+mod source;
 mod testcases;
+mod unsafe_code;
+mod unused_deps;
 
 pub fn split_user_repo(user_repo: &str) -> Result<[String; 2]> {
     let mut split = user_repo.split("/");
@@ -35,6 +54,7 @@ pub struct Repo {
     pub cargo_tomls: Vec<Utf8PathBuf>,
     pub workspaces: Workspaces,
     pub git_info: GitInfo,
+    pub git_repo: GitRepo,
 }
 
 impl Repo {
@@ -50,7 +70,8 @@ impl Repo {
 
         let workspaces = workspaces(&cargo_tomls)?;
 
-        let git_info = GitInfo::new(&dir)?;
+        let git_repo = GitRepo::open(&dir)?;
+        let git_info = GitInfo::new(&git_repo)?;
 
         Ok(Repo {
             user,
@@ -60,6 +81,7 @@ impl Repo {
             cargo_tomls,
             workspaces,
             git_info,
+            git_repo,
         })
     }
 
@@ -73,6 +95,51 @@ impl Repo {
             .collect()
     }
 
+    /// The metadata of the workspace the package is in.
+    fn pkg_metadata(&self, pkg: &Package) -> Option<&Metadata> {
+        self.workspaces
+            .values()
+            .find(|meta| meta.workspace_members.contains(&pkg.id))
+    }
+
+    /// Compare the public API with the last release, which must be just
+    /// downloaded by the index file.
+    fn semver_check(
+        &self,
+        pkg: &Package,
+        index_file: &IndexFile,
+    ) -> Result<Option<semver_check::SemverReport>> {
+        let meta = self.pkg_metadata(pkg).with_context(|| "no metadata")?;
+        let tarball = index_file.tarball.as_ref();
+        let baseline = &tarball.with_context(|| "no release")?.version;
+        let release_dir = index_file.unpack_last_release()?;
+        let report = semver_check::SemverReport::new(pkg, meta, &release_dir, baseline);
+        if let Some(run_dir) = release_dir.parent() {
+            _ = std::fs::remove_dir_all(run_dir);
+        }
+        report
+    }
+
+    /// Count unsafe code in the package and write locations to a side file.
+    fn unsafe_code(&self, pkg: &Package, output: &mut Output) -> Result<()> {
+        let scan = unsafe_code::scan_pkg(pkg);
+        output.unsafe_code = Some(scan.counts);
+
+        let mut side = serde_json::json!(scan);
+        if crate::config::unsafe_deps() {
+            if let Some(meta) = self.pkg_metadata(pkg) {
+                let (total, each) = unsafe_code::scan_deps(pkg, meta);
+                output.deps_unsafe_code = Some(total);
+                side["deps"] = serde_json::json!(each);
+            }
+        }
+
+        let path = local_output_dir(&self.user, &self.repo)
+            .join("unsafe")
+            .join(format!("{}.json", pkg.name));
+        write_json(&path, &side)
+    }
+
     fn contains_x64(&self, pkg: &str) -> bool {
         if let Some(targets) = self.pkg_targets.get(pkg) {
             for target in targets {
@@ -84,8 +151,10 @@ impl Repo {
         false
     }
 
-    fn get_pkg_tests(&self) -> Result<PkgTests> {
-        let mut map = PkgTests::new();
+    /// Errors in a workspace don't affect tests in other workspaces: they're
+    /// reported as warnings.
+    fn get_pkg_tests(&self) -> RepoTests {
+        let mut tests = RepoTests::default();
         for (workspace_root, meta) in &self.workspaces {
             // NOTE: nextest is run under all packages in a workspace,
             // maybe we should run tests for each package?
@@ -93,40 +162,166 @@ impl Repo {
             // we should tell them not run tests other than on x86_64-unknown-linux-gnu.
             'inner: for pkg in meta.workspace_packages() {
                 if self.contains_x64(&pkg.name) {
-                    map.extend(testcases::get(workspace_root)?);
+                    let log_dir = local_output_dir(&self.user, &self.repo);
+                    // listing tests builds test binaries, which tells compile
+                    // time and errors
+                    let (mut build, list) = build::list_tests(workspace_root, meta);
+                    let ws_tests =
+                        list.with_context(|| "failed to get test list")
+                            .and_then(|summary| {
+                                testcases::get(
+                                    workspace_root,
+                                    &meta.target_directory,
+                                    &log_dir,
+                                    summary,
+                                )
+                            });
+                    match ws_tests {
+                        Ok(ws_tests) => {
+                            tests.pkgs.extend(ws_tests.pkgs);
+                            tests.warnings.extend(ws_tests.warning);
+                        }
+                        Err(err) => {
+                            error!(?err, "Failed to get testcases");
+                            let err = strip_ansi_escapes::strip_str(format!("{err:?}"));
+                            tests.warnings.push(TestsWarning::new(workspace_root, err));
+                        }
+                    }
+                    for pkg in meta.workspace_packages() {
+                        let name = pkg.name.as_str();
+                        tests
+                            .build
+                            .insert(pkg.name.clone(), build.build_status(name));
+                        if build.success {
+                            tests
+                                .build_time
+                                .insert(pkg.name.clone(), build.build_time(name));
+                        }
+                    }
+                    if crate::config::coverage() {
+                        match coverage::run(workspace_root, meta) {
+                            Ok(cov) => {
+                                tests.coverage.extend(cov.pkgs);
+                                tests.lcov.push_str(&cov.lcov);
+                            }
+                            Err(err) => error!(?err, "Failed to collect coverage"),
+                        }
+                    }
                     break 'inner;
                 }
             }
         }
-        Ok(map)
+        tests
     }
 
     pub fn output(&self) -> Result<serde_json::Value> {
-        let mut test_cases = self
-            .get_pkg_tests()
-            .inspect_err(|err| error!(?err, "Failed to get testcases"))
-            .unwrap_or_default();
+        let RepoTests {
+            pkgs: mut test_cases,
+            warnings: mut test_warnings,
+            mut build,
+            mut build_time,
+            mut coverage,
+            lcov,
+        } = self.get_pkg_tests();
+        if !lcov.is_empty() {
+            let path = local_output_dir(&self.user, &self.repo).join("coverage.lcov");
+            if let Err(err) = write_file(&path, &lcov) {
+                error!(?err, ?path, "Failed to write the lcov report");
+            }
+        }
         let pkgs = self.packages();
 
         let last_commit_time = self.git_info.last_commit.to_string();
+        let tags = self
+            .git_repo
+            .tags()
+            .inspect_err(|err| error!(?err, "Failed to read git tags"))
+            .ok();
+        let single_pkg = pkgs.len() == 1;
+        // dirs of all packages, including ones not checked by os-checker,
+        // since a package nested in another package dir is excluded from it
+        let mut pkg_dirs: Vec<_> = self
+            .workspaces
+            .values()
+            .flat_map(|ws| ws.workspace_packages())
+            .filter_map(|pkg| pkg.manifest_path.parent())
+            .collect();
+        pkg_dirs.sort_unstable();
+        pkg_dirs.dedup();
+        let mut git_stats: IndexMap<_, _> = match PkgGitStats::new(&self.git_repo, &pkg_dirs) {
+            Ok(stats) => pkg_dirs.into_iter().zip(stats).collect(),
+            Err(err) => {
+                error!(?err, "Failed to read git log of packages");
+                IndexMap::new()
+            }
+        };
 
         let mut outputs = IndexMap::with_capacity(pkgs.len());
         for pkg in pkgs {
             let pkg_name = pkg.name.as_str();
             let _span = error_span!("output", pkg = pkg_name).entered();
 
+            let pkg_dir = pkg.manifest_path.parent().unwrap();
+            let git = git_stats.swap_remove(pkg_dir).flatten();
+            // the repo-wide time is only used when the package has no history
+            let last_commit_time = git
+                .as_ref()
+                .map_or_else(|| last_commit_time.clone(), |g| g.last_commit.to_string());
+
             let mut output = Output::new(pkg, test_cases.swap_remove(pkg_name), &last_commit_time);
+            output.git = git;
+            output.build_status = build.swap_remove(pkg_name);
+            output.build_time = build_time.swap_remove(pkg_name);
+            output.coverage = coverage.swap_remove(pkg_name);
+            output.source = Some(source::SourceStats::new(pkg));
+            if let Some(meta) = self.pkg_metadata(pkg) {
+                output.deps = Some(deps::Dependencies::new(pkg, meta));
+                output.advisories = advisory::AdvisoryReport::new(pkg, meta);
+                if crate::config::outdated() {
+                    output.outdated = Some(outdated::Outdated::new(pkg, meta));
+                }
+                if crate::config::unused_deps() {
+                    output.unused_deps = unused_deps::UnusedDeps::new(pkg, meta)
+                        .inspect_err(|err| error!(?err, "Failed to check unused deps"))
+                        .ok();
+                }
+                output.license = Some(license::License::new(pkg, meta));
+                output.msrv = msrv::Msrv::new(pkg, meta);
+                if crate::config::feature_powerset() {
+                    output.features = Some(features::FeatureCheck::new(pkg, meta));
+                }
+                if crate::config::docs() {
+                    output.docs = docs::Docs::new(pkg, meta)
+                        .inspect_err(|err| error!(?err, "Failed to build docs"))
+                        .ok();
+                }
+            }
+            if let Err(err) = self.unsafe_code(pkg, &mut output) {
+                error!(?err, "Failed to write unsafe code inventory");
+            }
             output.diag_total_count = diag_total_count([&self.user, &self.repo, pkg_name]);
 
-            match IndexFile::new(pkg_name) {
+            output.popularity = crate::crates_io::crate_stats(pkg_name);
+
+            match IndexFile::for_pkg(pkg) {
                 Ok(mut index_file) => {
                     output.release_count = Some(index_file.release_count());
+                    output.release_tags = tags.as_deref().map(|tags| {
+                        release_tags::ReleaseTags::new(pkg_name, &index_file, tags, single_pkg)
+                    });
                     match index_file.get_last_release_info() {
                         Ok(()) => {
                             if let Some((size, time)) = index_file.last_release_size_and_time() {
                                 output.last_release_size = Some(size);
                                 output.last_release_time = Some(time.to_string());
                             }
+                            if crate::config::semver() {
+                                output.semver = self
+                                    .semver_check(pkg, &index_file)
+                                    .inspect_err(|err| error!(?err, "Failed to check semver"))
+                                    .ok()
+                                    .flatten();
+                            }
                         }
                         Err(err) => error!(?err),
                     }
@@ -142,6 +337,28 @@ impl Repo {
 
         outputs.sort_unstable_keys();
 
+        // tests of packages not emitted, e.g. not checked by os-checker
+        if !test_cases.is_empty() {
+            let mut warning = TestsWarning::new(
+                &self.dir,
+                format!(
+                    "tests of {} packages are not attributed to any package in the output",
+                    test_cases.len()
+                ),
+            );
+            for (pkg, tests) in &test_cases {
+                for bin in &tests.tests {
+                    let bin_name = &bin.binary_name;
+                    warning.unattributed.extend(
+                        bin.testcases
+                            .iter()
+                            .map(|case| format!("{pkg}::{bin_name}${}", case.name)),
+                    );
+                }
+            }
+            test_warnings.push(warning);
+        }
+
         let now = os_checker_types::now();
         let json = serde_json::json!({
             "user": self.user,
@@ -150,7 +367,8 @@ impl Repo {
                 "start": now,
                 "end": now
             },
-            "pkgs": outputs
+            "pkgs": outputs,
+            "test_warnings": test_warnings
         });
 
         Ok(json)
@@ -162,12 +380,40 @@ impl Repo {
     }
 }
 
+/// Tests and their build status in all workspaces of a repo.
+#[derive(Default)]
+struct RepoTests {
+    pkgs: PkgTests,
+    warnings: Vec<TestsWarning>,
+    /// None for packages whose tests are not run
+    build: IndexMap<String, BuildStatus>,
+    build_time: IndexMap<String, BuildTime>,
+    /// empty if coverage is not enabled
+    coverage: IndexMap<String, Coverage>,
+    /// lcov reports of all workspaces
+    lcov: String,
+}
+
 pub fn write_output_json(user: &str, repo: &str, json: &serde_json::Value) -> Result<()> {
-    let mut path = Utf8PathBuf::from_iter([crate::BASE_DIR, user, repo]);
+    let mut path = local_output_dir(user, repo);
     path.set_extension("json");
     write_json(&path, json)
 }
 
+/// Write a text file, creating parent dirs if absent.
+pub fn write_file(path: &Utf8Path, text: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// The dir for side files of a repo: `cargo/<user>/<repo>/`.
+pub fn local_output_dir(user: &str, repo: &str) -> Utf8PathBuf {
+    Utf8PathBuf::from_iter([crate::BASE_DIR, user, repo])
+}
+
 pub fn local_base_dir() -> &'static Utf8Path {
     static GIT_CLONE_DIR: LazyLock<Utf8PathBuf> = LazyLock::new(|| {
         let path = Utf8PathBuf::from_iter(["/tmp", "os-checker-plugin-cargo"]);
@@ -180,6 +426,14 @@ pub fn local_base_dir() -> &'static Utf8Path {
     &GIT_CLONE_DIR
 }
 
+/// `$CARGO_HOME`, or `~/.cargo` if it's unset.
+pub fn cargo_home() -> Option<Utf8PathBuf> {
+    std::env::var("CARGO_HOME")
+        .or_else(|_| std::env::var("HOME").map(|home| format!("{home}/.cargo")))
+        .map(Utf8PathBuf::from)
+        .ok()
+}
+
 // dependes on where does os-checker put the repo
 pub fn local_repo_dir(user: &str, repo: &str) -> Utf8PathBuf {
     let mut dir = local_base_dir().to_owned();
@@ -229,6 +483,16 @@ fn workspaces(cargo_tomls: &[Utf8PathBuf]) -> Result<Workspaces> {
     Ok(map)
 }
 
+/// Metadata of the fixture workspace at `tests/fixtures/ws`: the root
+/// package `app`, and members `corelib`, `extra` and `helper`.
+#[cfg(test)]
+fn fixture_metadata() -> Metadata {
+    cargo_metadata::MetadataCommand::new()
+        .manifest_path("tests/fixtures/ws/Cargo.toml")
+        .exec()
+        .unwrap()
+}
+
 #[test]
 fn test_cargo_tomls() {
     dbg!(get_cargo_tomls_recursively(Utf8Path::new(".")));
@@ -0,0 +1,128 @@
+use super::check_errors::check_result;
+use cargo_metadata::{semver::Version, Package};
+use plugin::prelude::*;
+use std::sync::LazyLock;
+
+/// Configured toolchains with their rustc versions, in ascending order.
+static TOOLCHAINS: LazyLock<Vec<(String, Version)>> = LazyLock::new(|| {
+    let mut v: Vec<_> = crate::config::msrv_toolchains()
+        .into_iter()
+        .filter_map(|toolchain| {
+            let version = rustc_version(&toolchain)
+                .inspect_err(|err| error!(?err, toolchain, "Skip a toolchain for MSRV"))
+                .ok()?;
+            Some((toolchain, version))
+        })
+        .collect();
+    v.sort_by(|a, b| a.1.cmp(&b.1));
+    v
+});
+
+/// `rustc 1.70.0 (90c541806 2023-05-31)`
+fn rustc_version(toolchain: &str) -> Result<Version> {
+    let output = cmd!("rustc", format!("+{toolchain}"), "--version").read()?;
+    parse_rustc_version(&output)
+}
+
+/// `rustc 1.80.0 (051478957 2024-07-21)`
+fn parse_rustc_version(output: &str) -> Result<Version> {
+    let version = output
+        .split_whitespace()
+        .nth(1)
+        .with_context(|| format!("Unexpected rustc version: {output}"))?;
+    Ok(version.parse()?)
+}
+
+/// Minimum supported Rust version of a package.
+#[derive(Debug, Serialize)]
+pub struct Msrv {
+    /// `rust-version` in the manifest
+    pub declared: Option<String>,
+    /// whether the package checks with the declared version; None if no
+    /// MSRV is declared or no toolchain matches it
+    pub verified: Option<bool>,
+    /// lowest configured toolchain that checks the package if no MSRV is
+    /// declared
+    pub detected: Option<String>,
+    /// errors from `cargo check` if the declared MSRV fails
+    pub error: Option<String>,
+}
+
+impl Msrv {
+    /// Check the package with configured toolchains. Returns None if no
+    /// toolchain is configured.
+    pub fn new(pkg: &Package, meta: &Metadata) -> Option<Self> {
+        if TOOLCHAINS.is_empty() {
+            return None;
+        }
+        let _span = error_span!("msrv", pkg = pkg.name.as_str()).entered();
+
+        let mut msrv = Msrv {
+            declared: pkg.rust_version.as_ref().map(|v| v.to_string()),
+            verified: None,
+            detected: None,
+            error: None,
+        };
+        match &pkg.rust_version {
+            Some(declared) => {
+                // a toolchain of the same minor version, e.g. 1.70.0 for 1.70
+                let toolchain = TOOLCHAINS
+                    .iter()
+                    .find(|(_, v)| v.major == declared.major && v.minor == declared.minor);
+                if let Some((toolchain, _)) = toolchain {
+                    let result = check(pkg, meta, toolchain);
+                    msrv.verified = Some(result.is_ok());
+                    msrv.error = result.err();
+                } else {
+                    warn!(%declared, "No toolchain matches the declared MSRV");
+                }
+            }
+            None => {
+                // binary search the lowest passing toolchain, assuming newer
+                // toolchains pass if an older one does
+                let (mut lo, mut hi) = (0, TOOLCHAINS.len());
+                while lo < hi {
+                    let mid = (lo + hi) / 2;
+                    if check(pkg, meta, &TOOLCHAINS[mid].0).is_ok() {
+                        hi = mid;
+                    } else {
+                        lo = mid + 1;
+                    }
+                }
+                msrv.detected = TOOLCHAINS.get(lo).map(|(_, v)| v.to_string());
+            }
+        }
+        Some(msrv)
+    }
+}
+
+/// `cargo +toolchain check` the package, with a target dir for each
+/// toolchain. Returns the tail of stderr on failure.
+fn check(pkg: &Package, meta: &Metadata, toolchain: &str) -> Result<(), String> {
+    let target_dir = meta
+        .target_directory
+        .join(format!("os-checker-msrv-{toolchain}"));
+    let output = cmd!(
+        "cargo",
+        format!("+{toolchain}"),
+        "check",
+        "--manifest-path",
+        &pkg.manifest_path
+    )
+    .dir(&meta.workspace_root)
+    .env("CARGO_TARGET_DIR", target_dir)
+    .stdout_null()
+    .stderr_capture()
+    .unchecked()
+    .run()
+    .map_err(|err| err.to_string())?;
+    check_result(&output)
+}
+
+#[test]
+fn parse_rustc_versions() {
+    let version = parse_rustc_version("rustc 1.80.0 (051478957 2024-07-21)").unwrap();
+    assert_eq!(version, Version::new(1, 80, 0));
+    assert!(parse_rustc_version("rustc 1.83.0-nightly (abc 2024-10-01)").is_ok());
+    assert!(parse_rustc_version("error: toolchain not installed").is_err());
+}
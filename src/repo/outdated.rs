@@ -0,0 +1,176 @@
+use crate::crates_io::IndexFile;
+use cargo_metadata::{
+    semver::{Version, VersionReq},
+    DependencyKind, Package,
+};
+use plugin::prelude::*;
+use std::sync::{LazyLock, Mutex};
+
+/// Available versions of a crate, or the error to read its index file.
+type Versions = Result<Vec<Version>, String>;
+
+/// Versions of crates read from the index, shared by packages in a repo.
+static VERSIONS: LazyLock<Mutex<IndexMap<String, Versions>>> = LazyLock::new(Default::default);
+
+/// The index file is fetched without holding the lock, so lookups of
+/// different crates don't wait for each other.
+fn versions(name: &str) -> Versions {
+    if let Some(versions) = VERSIONS.lock().unwrap().get(name) {
+        return versions.clone();
+    }
+    let versions = IndexFile::new(name)
+        .map(|index_file| index_file.available_versions().cloned().collect())
+        .map_err(|err| err.to_string());
+    VERSIONS
+        .lock()
+        .unwrap()
+        .entry(name.to_owned())
+        .or_insert(versions)
+        .clone()
+}
+
+/// A direct dependency whose requirement doesn't accept the newest version.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct OutdatedDep {
+    pub name: String,
+    pub kind: String,
+    pub req: String,
+    /// the version in the lockfile
+    pub locked: Option<String>,
+    /// newest version matching the requirement
+    pub compatible: Option<String>,
+    pub latest: String,
+    /// the latest version is semver-incompatible with the compatible one
+    pub major_behind: bool,
+}
+
+/// Direct dependencies from crates.io compared with the index.
+#[derive(Debug, Default, Serialize)]
+pub struct Outdated {
+    pub checked: usize,
+    pub outdated: Vec<OutdatedDep>,
+    pub major_behind: usize,
+    /// dependencies whose index files can't be read
+    pub errors: Vec<String>,
+}
+
+impl Outdated {
+    pub fn new(pkg: &Package, meta: &Metadata) -> Self {
+        let _span = error_span!("outdated", pkg = pkg.name.as_str()).entered();
+
+        let mut report = Outdated::default();
+        let mut seen = Vec::new();
+        for dep in &pkg.dependencies {
+            let from_crates_io = dep.path.is_none()
+                && dep.registry.is_none()
+                && dep
+                    .source
+                    .as_deref()
+                    .is_some_and(|s| s.starts_with("registry+"));
+            let key = (&dep.name, &dep.req, dep.kind);
+            if !from_crates_io || seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            report.checked += 1;
+
+            let versions = match versions(&dep.name) {
+                Ok(v) => v,
+                Err(err) => {
+                    report.errors.push(format!("{}: {err}", dep.name));
+                    continue;
+                }
+            };
+            let locked = locked_version(pkg, meta, &dep.name, &dep.req);
+            if let Some(outdated) = compare(&dep.req, &versions) {
+                report.outdated.push(OutdatedDep {
+                    name: dep.name.clone(),
+                    kind: kind_name(dep.kind).to_owned(),
+                    req: dep.req.to_string(),
+                    locked: locked.map(|v| v.to_string()),
+                    compatible: outdated.0.map(|v| v.to_string()),
+                    latest: outdated.1.to_string(),
+                    major_behind: outdated.2,
+                });
+            }
+        }
+        report.major_behind = report.outdated.iter().filter(|d| d.major_behind).count();
+        report
+    }
+}
+
+fn kind_name(kind: DependencyKind) -> &'static str {
+    match kind {
+        DependencyKind::Development => "dev",
+        DependencyKind::Build => "build",
+        _ => "normal",
+    }
+}
+
+/// The resolved version of a direct dependency.
+fn locked_version<'a>(
+    pkg: &Package,
+    meta: &'a Metadata,
+    name: &str,
+    req: &VersionReq,
+) -> Option<&'a Version> {
+    let node = meta
+        .resolve
+        .as_ref()?
+        .nodes
+        .iter()
+        .find(|n| n.id == pkg.id)?;
+    node.deps
+        .iter()
+        .filter_map(|d| meta.packages.iter().find(|p| p.id == d.pkg))
+        .find(|p| p.name == name && req.matches(&p.version))
+        .map(|p| &p.version)
+}
+
+/// Returns the newest compatible version, the latest version and whether
+/// it's a major bump, if the requirement doesn't accept the latest one.
+///
+/// Pre-releases are ignored unless the requirement asks for them.
+fn compare<'a>(
+    req: &VersionReq,
+    versions: &'a [Version],
+) -> Option<(Option<&'a Version>, &'a Version, bool)> {
+    let wants_pre = req.comparators.iter().any(|c| !c.pre.is_empty());
+    let versions = || versions.iter().filter(|v| wants_pre || v.pre.is_empty());
+    let latest = versions().max()?;
+    if req.matches(latest) {
+        return None;
+    }
+    let compatible = versions().filter(|v| req.matches(v)).max();
+    let major_behind = compatible.is_none_or(|v| compat_key(v) != compat_key(latest));
+    Some((compatible, latest, major_behind))
+}
+
+/// Versions with the same key are semver-compatible: the leftmost non-zero
+/// component decides.
+fn compat_key(v: &Version) -> (u64, u64, u64) {
+    match (v.major, v.minor) {
+        (0, 0) => (0, 0, v.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+#[test]
+fn outdated_versions() {
+    let versions: Vec<Version> = ["0.1.0", "0.2.0", "0.2.5", "1.0.0", "1.1.0", "2.0.0-alpha.1"]
+        .iter()
+        .map(|v| v.parse().unwrap())
+        .collect();
+    let req = |s: &str| VersionReq::parse(s).unwrap();
+
+    assert_eq!(compare(&req("1"), &versions), None);
+    let (compatible, latest, major) = compare(&req("0.2"), &versions).unwrap();
+    assert_eq!(compatible.unwrap().to_string(), "0.2.5");
+    assert_eq!(latest.to_string(), "1.1.0");
+    assert!(major);
+    let (compatible, _, major) = compare(&req("~1.0"), &versions).unwrap();
+    assert_eq!(compatible.unwrap().to_string(), "1.0.0");
+    assert!(!major);
+    assert!(compare(&req("3"), &versions).unwrap().0.is_none());
+}
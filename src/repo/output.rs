@@ -1,4 +1,22 @@
-use super::testcases::TestCases;
+use super::{
+    advisory::AdvisoryReport,
+    build::{BuildStatus, BuildTime},
+    coverage::Coverage,
+    deps::Dependencies,
+    docs::Docs,
+    features::FeatureCheck,
+    git_info::PkgGitStats,
+    license::License,
+    msrv::Msrv,
+    outdated::Outdated,
+    release_tags::ReleaseTags,
+    semver_check::SemverReport,
+    source::SourceStats,
+    testcases::TestCases,
+    unsafe_code::UnsafeCounts,
+    unused_deps::UnusedDeps,
+};
+use crate::crates_io::CrateStats;
 use cargo_metadata::Package;
 use plugin::prelude::*;
 use serde::Serialize;
@@ -7,26 +25,66 @@ use serde::Serialize;
 pub struct Output {
     pub version: String,
     pub dependencies: usize,
+    /// dependencies by kind and source, and duplicates in the resolved graph
+    pub deps: Option<Dependencies>,
+    /// RustSec advisories and yanked releases in dependencies; None if the
+    /// advisory database is not configured
+    pub advisories: Option<AdvisoryReport>,
+    /// dependencies never used by the targets they are available to; None
+    /// if disabled or the check fails
+    pub unused_deps: Option<UnusedDeps>,
+    /// direct dependencies behind the newest version in the index; None if
+    /// disabled
+    pub outdated: Option<Outdated>,
     pub lib: bool,
     pub bin: bool,
     pub testcases: Option<TestCases>,
+    /// whether test binaries compile; None if tests are not run
+    pub build_status: Option<BuildStatus>,
+    /// compile time of test binaries; None if tests are not run
+    pub build_time: Option<BuildTime>,
+    /// failing feature combinations; None if not enabled
+    pub features: Option<FeatureCheck>,
+    /// None if coverage is not enabled or fails
+    pub coverage: Option<Coverage>,
+    /// lines of code and tests
+    pub source: Option<SourceStats>,
+    pub unsafe_code: Option<UnsafeCounts>,
+    /// sum of unsafe usage in dependencies; None if not enabled
+    pub deps_unsafe_code: Option<UnsafeCounts>,
     pub tests: usize,
     pub examples: usize,
     pub benches: usize,
     pub authors: Vec<String>,
+    /// license expression, license files and licenses of dependencies
+    pub license: Option<License>,
     pub description: String,
     pub documentation: Option<String>,
+    /// result of `cargo doc --no-deps`; None if disabled or fails to run
+    pub docs: Option<Docs>,
     pub readme: Option<String>,
     pub homepage: Option<String>,
     pub keywords: Vec<String>,
     pub categories: Vec<String>,
     pub rust_version: Option<String>,
+    /// declared, verified and detected MSRV; None if no toolchain is
+    /// configured
+    pub msrv: Option<Msrv>,
     pub diag_total_count: Option<usize>,
+    /// time of the last commit touching the package dir
     pub last_commit_time: String,
+    /// commits and authors of the package dir; None if it has no history
+    pub git: Option<PkgGitStats>,
     /// crates.io 发版次数
     pub release_count: Option<usize>,
     pub last_release_size: Option<u64>,
     pub last_release_time: Option<String>,
+    /// releases matched with git tags; None if tags can't be read
+    pub release_tags: Option<ReleaseTags>,
+    /// downloads, reverse dependencies and owners from the db-dump
+    pub popularity: Option<CrateStats>,
+    /// breaking changes since the last release; None if disabled or fails
+    pub semver: Option<SemverReport>,
 }
 
 impl Output {
@@ -34,25 +92,43 @@ impl Output {
         Output {
             version: pkg.version.to_string(),
             testcases,
+            build_status: None,
+            build_time: None,
+            features: None,
+            coverage: None,
+            source: None,
+            unsafe_code: None,
+            deps_unsafe_code: None,
             dependencies: pkg.dependencies.len(),
+            deps: None,
+            advisories: None,
+            unused_deps: None,
+            outdated: None,
             lib: pkg.targets.iter().any(|t| t.is_lib()),
             bin: pkg.targets.iter().any(|t| t.is_bin()),
             tests: pkg.targets.iter().filter(|t| t.is_test()).count(),
             examples: pkg.targets.iter().filter(|t| t.is_example()).count(),
             benches: pkg.targets.iter().filter(|t| t.is_bench()).count(),
             authors: pkg.authors.clone(),
+            license: None,
             description: pkg.description.clone().unwrap_or_default(),
             documentation: pkg.documentation.clone(),
+            docs: None,
             readme: pkg.readme.as_deref().map(|p| p.to_string()),
             homepage: pkg.homepage.clone(),
             keywords: pkg.keywords.clone(),
             categories: pkg.categories.clone(),
             rust_version: pkg.rust_version.clone().map(|v| v.to_string()),
+            msrv: None,
             diag_total_count: None,
             last_commit_time: last_commit_time.to_owned(),
+            git: None,
             release_count: None,
             last_release_size: None,
             last_release_time: None,
+            release_tags: None,
+            popularity: None,
+            semver: None,
         }
     }
 }
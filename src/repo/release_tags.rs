@@ -0,0 +1,144 @@
+use super::git_info::Tag;
+use crate::crates_io::IndexFile;
+use cargo_metadata::semver::Version;
+use plugin::prelude::*;
+
+/// A release in the index and the tag of it.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ReleaseTag {
+    pub version: String,
+    pub yanked: bool,
+    pub tag: Option<String>,
+    /// the commit the tag points to
+    pub commit: Option<String>,
+}
+
+/// Releases of a package matched with tags in the repo.
+#[derive(Debug, Default, Serialize)]
+pub struct ReleaseTags {
+    pub releases: Vec<ReleaseTag>,
+    pub untagged_releases: Vec<String>,
+    /// tags of the package whose versions are never released
+    pub unreleased_tags: Vec<String>,
+}
+
+impl ReleaseTags {
+    /// Tags like `v1.2.3` without a package name match releases of any
+    /// package, but are only reported as unreleased if the repo has a
+    /// single package.
+    pub fn new(pkg: &str, index_file: &IndexFile, tags: &[Tag], single_pkg: bool) -> Self {
+        // (tag, version, whether the tag has the package name)
+        let tags: Vec<_> = tags
+            .iter()
+            .filter_map(|tag| {
+                let (name, version) = parse_tag(&tag.name)?;
+                match name {
+                    Some(name) if name == pkg => Some((tag, version, true)),
+                    Some(_) => None,
+                    None => Some((tag, version, false)),
+                }
+            })
+            .collect();
+
+        let mut report = ReleaseTags::default();
+        for data in &index_file.data {
+            // a tag with the package name is preferred
+            let tag = tags
+                .iter()
+                .filter(|(_, version, _)| *version == data.vers)
+                .max_by_key(|(_, _, named)| *named);
+            if tag.is_none() {
+                report.untagged_releases.push(data.vers.to_string());
+            }
+            report.releases.push(ReleaseTag {
+                version: data.vers.to_string(),
+                yanked: data.yanked,
+                tag: tag.map(|(tag, _, _)| tag.name.clone()),
+                commit: tag.and_then(|(tag, _, _)| tag.commit.clone()),
+            });
+        }
+        report.unreleased_tags = tags
+            .iter()
+            .filter(|(_, version, named)| {
+                (*named || single_pkg) && !index_file.data.iter().any(|d| d.vers == *version)
+            })
+            .map(|(tag, _, _)| tag.name.clone())
+            .collect();
+        report
+    }
+}
+
+/// Parse `v1.2.3`, `1.2.3`, `name-v1.2.3` and `name@1.2.3` into the
+/// package name if any and the version.
+fn parse_tag(tag: &str) -> Option<(Option<&str>, Version)> {
+    let version = |s: &str| Version::parse(s.strip_prefix('v').unwrap_or(s)).ok();
+    if let Some(v) = version(tag) {
+        return Some((None, v));
+    }
+    if let Some((name, v)) = tag.rsplit_once('@') {
+        return Some((Some(name), version(v)?));
+    }
+    // the name may contain `-v`, e.g. `foo-vec-v0.1.0`
+    tag.match_indices("-v").find_map(|(idx, _)| {
+        let (name, v) = tag.split_at(idx);
+        Some((Some(name), version(&v[1..])?))
+    })
+}
+
+#[test]
+fn release_tags() {
+    use crate::crates_io::{Data, Registry};
+
+    let v = |s: &str| Version::parse(s).unwrap();
+    assert_eq!(parse_tag("v1.2.3"), Some((None, v("1.2.3"))));
+    assert_eq!(parse_tag("foo-v1.2.3"), Some((Some("foo"), v("1.2.3"))));
+    assert_eq!(
+        parse_tag("foo-vec-v0.1.0-alpha.1"),
+        Some((Some("foo-vec"), v("0.1.0-alpha.1")))
+    );
+    assert_eq!(parse_tag("foo@0.1.0"), Some((Some("foo"), v("0.1.0"))));
+    assert_eq!(parse_tag("release-2024"), None);
+
+    let data = |vers: &str| Data {
+        vers: v(vers),
+        yanked: false,
+        cksum: String::new(),
+    };
+    let index_file = IndexFile {
+        pkg: "foo".to_owned(),
+        data: vec![data("0.1.0"), data("0.2.0"), data("0.3.0")],
+        tarball: None,
+        registry: Registry::crates_io(),
+    };
+    let tag = |name: &str, commit: &str| Tag {
+        name: name.to_owned(),
+        commit: Some(commit.to_owned()),
+    };
+    let tags = [
+        tag("v0.1.0", "c1"),
+        tag("foo-v0.1.0", "c2"),
+        tag("foo@0.3.0", "c3"),
+        tag("bar-v0.2.0", "c4"),
+        tag("foo-v0.4.0", "c5"),
+        tag("v0.5.0", "c6"),
+    ];
+
+    let report = ReleaseTags::new("foo", &index_file, &tags, false);
+    assert_eq!(report.releases[0].tag.as_deref(), Some("foo-v0.1.0"));
+    assert_eq!(report.releases[0].commit.as_deref(), Some("c2"));
+    assert_eq!(report.releases[2].commit.as_deref(), Some("c3"));
+    assert_eq!(report.untagged_releases, ["0.2.0"]);
+    assert_eq!(report.unreleased_tags, ["foo-v0.4.0"]);
+
+    let report = ReleaseTags::new("foo", &index_file, &tags, true);
+    assert_eq!(report.unreleased_tags, ["foo-v0.4.0", "v0.5.0"]);
+
+    // `v0.1.0` contains the name of `v0` but isn't a tag of the package name
+    let index_file = IndexFile {
+        pkg: "v0".to_owned(),
+        ..index_file
+    };
+    let tags = [tag("v0-v0.1.0", "c1"), tag("v0.1.0", "c2")];
+    let report = ReleaseTags::new("v0", &index_file, &tags, false);
+    assert_eq!(report.releases[0].tag.as_deref(), Some("v0-v0.1.0"));
+}
@@ -0,0 +1,227 @@
+use crate::nextest::{run_testcases_with, tail, Event, Report, TestRecord};
+use plugin::prelude::*;
+use std::{fmt, str::FromStr};
+
+/// Sanitizers to complement Miri on tests using inline asm or FFI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitizer {
+    Address,
+    Thread,
+    Leak,
+}
+
+impl FromStr for Sanitizer {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            "address" => Sanitizer::Address,
+            "thread" => Sanitizer::Thread,
+            "leak" => Sanitizer::Leak,
+            _ => bail!("Unsupported sanitizer `{s}`: only address, thread and leak are supported."),
+        })
+    }
+}
+
+impl fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Leak => "leak",
+        })
+    }
+}
+
+/// Test results of a sanitizer run in a workspace.
+pub struct SanitizerReport {
+    pub sanitizer: Sanitizer,
+    pub report: Report,
+}
+
+/// Rerun the nextest suite on nightly with the sanitizer and std rebuilt.
+///
+/// A separate target dir is used for each sanitizer to not invalidate the
+/// normal build.
+pub fn run(
+    workspace_root: &Utf8Path,
+    target_dir: &Utf8Path,
+    sanitizer: Sanitizer,
+) -> Result<SanitizerReport> {
+    let _span = error_span!("sanitizer", %sanitizer, ?workspace_root).entered();
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+    let flags = format!("-Zsanitizer={sanitizer}");
+    let target_dir = target_dir.join(format!("os-checker-sanitizer-{sanitizer}"));
+    let report = run_testcases_with(
+        workspace_root,
+        &["--target", TARGET, "-Zbuild-std"],
+        &[
+            ("RUSTFLAGS", &flags),
+            ("RUSTDOCFLAGS", &flags),
+            ("CARGO_TARGET_DIR", target_dir.as_str()),
+        ],
+    )?;
+    Ok(SanitizerReport { sanitizer, report })
+}
+
+/// Sanitizer result of a testcase, in the same shape as Miri results.
+#[derive(Debug, Serialize)]
+pub struct SanitizerResult {
+    pub sanitizer: Sanitizer,
+    pub pass: bool,
+    pub findings: Vec<Finding>,
+    /// tail of the test output if the test fails
+    pub output: Option<String>,
+}
+
+impl SanitizerResult {
+    pub fn new(sanitizer: Sanitizer, record: &TestRecord, limit: usize) -> Self {
+        let pass = record.event == Event::Ok;
+        let full = (!pass).then(|| record.full_output());
+        let findings = full.as_deref().map(parse_findings).unwrap_or_default();
+        let output = full.map(|text| tail(&text, limit).0.to_owned());
+        SanitizerResult {
+            sanitizer,
+            pass,
+            findings,
+            output,
+        }
+    }
+}
+
+/// An error report from a sanitizer.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Finding {
+    /// which sanitizer emits the report, e.g. AddressSanitizer
+    pub tool: String,
+    /// e.g. heap-use-after-free, data race or detected memory leaks
+    pub kind: String,
+    pub frames: Vec<Frame>,
+    /// `file:line:col` of the first frame with source location
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Frame {
+    pub function: String,
+    pub location: Option<String>,
+}
+
+/// Parse reports like
+///
+/// ```text
+/// ==1==ERROR: AddressSanitizer: heap-use-after-free on address 0x6020 at pc 0x55d
+/// READ of size 4 at 0x6020 thread T0
+///     #0 0x55d in foo::bar /src/lib.rs:10:5
+/// WARNING: ThreadSanitizer: data race (pid=1)
+///     #0 foo::baz /src/lib.rs:20:9 (t1+0xd4)
+/// ```
+pub fn parse_findings(text: &str) -> Vec<Finding> {
+    let mut findings = Vec::<Finding>::new();
+    let mut in_stack = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(finding) = parse_header(trimmed) {
+            findings.push(finding);
+            in_stack = true;
+        } else if trimmed.starts_with("SUMMARY: ") {
+            in_stack = false;
+        } else if let (true, Some(finding)) = (in_stack, findings.last_mut()) {
+            if let Some(frame) = parse_frame(trimmed) {
+                if finding.location.is_none() && is_user_code(frame.location.as_deref()) {
+                    finding.location = frame.location.clone();
+                }
+                finding.frames.push(frame);
+            }
+        }
+    }
+    findings
+}
+
+fn parse_header(line: &str) -> Option<Finding> {
+    // ==pid==ERROR: AddressSanitizer: kind on address ...
+    // WARNING: ThreadSanitizer: kind (pid=...)
+    let rest = match line.find("ERROR: ") {
+        Some(pos) => &line[pos + "ERROR: ".len()..],
+        None => line.strip_prefix("WARNING: ")?,
+    };
+    let (tool, kind) = rest.split_once(": ")?;
+    if !tool.ends_with("Sanitizer") {
+        return None;
+    }
+    let kind = kind
+        .split(" on ")
+        .next()
+        .unwrap_or(kind)
+        .split(" (pid=")
+        .next()
+        .unwrap_or(kind);
+    Some(Finding {
+        tool: tool.to_owned(),
+        kind: kind.trim().to_owned(),
+        frames: Vec::new(),
+        location: None,
+    })
+}
+
+/// `#0 0x55d in foo::bar /src/lib.rs:10:5` or `#0 foo::bar /src/lib.rs:10:5 (bin+0x1)`
+fn parse_frame(line: &str) -> Option<Frame> {
+    let rest = line.strip_prefix('#')?;
+    let (_, rest) = rest.split_once(' ')?;
+    let rest = match rest.strip_prefix("0x") {
+        Some(addr) => addr.split_once(" in ").map_or("", |(_, rest)| rest),
+        None => rest,
+    };
+    let mut words = rest.split_whitespace();
+    let function = words.next()?.to_owned();
+    let location = words
+        .next()
+        .filter(|loc| !loc.starts_with('('))
+        .map(str::to_owned);
+    Some(Frame { function, location })
+}
+
+/// Skip frames in std, compiler runtimes and dependencies.
+fn is_user_code(location: Option<&str>) -> bool {
+    location.is_some_and(|loc| {
+        loc.contains(".rs:")
+            && !loc.contains("/rustc/")
+            && !loc.contains("/library/")
+            && !loc.contains("/.cargo/registry/")
+    })
+}
+
+#[test]
+fn parse_sanitizer_reports() {
+    let text = "
+==1234==ERROR: AddressSanitizer: heap-use-after-free on address 0x602000000010 at pc 0x55d
+READ of size 4 at 0x602000000010 thread T0
+    #0 0x55d in core::ptr::read /rustc/abc/library/core/src/ptr/mod.rs:1:1
+    #1 0x56e in t1::uaf /work/tests/t1.rs:12:14
+    #2 0x57f in main (/work/target/t1+0x1)
+SUMMARY: AddressSanitizer: heap-use-after-free /work/tests/t1.rs:12:14 in t1::uaf
+==================
+WARNING: ThreadSanitizer: data race (pid=42)
+  Write of size 4 at 0x7b04 by thread T1:
+    #0 t1::race::{{closure}} /work/tests/t1.rs:20:9 (t1+0xd4)
+";
+    let findings = parse_findings(text);
+    dbg!(&findings);
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].tool, "AddressSanitizer");
+    assert_eq!(findings[0].kind, "heap-use-after-free");
+    assert_eq!(findings[0].frames.len(), 3);
+    assert_eq!(findings[0].frames[2].location, None);
+    assert_eq!(
+        findings[0].location.as_deref(),
+        Some("/work/tests/t1.rs:12:14")
+    );
+    assert_eq!(findings[1].kind, "data race");
+    assert_eq!(findings[1].frames[0].function, "t1::race::{{closure}}");
+    assert_eq!(
+        findings[1].location.as_deref(),
+        Some("/work/tests/t1.rs:20:9")
+    );
+}
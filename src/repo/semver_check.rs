@@ -0,0 +1,474 @@
+use cargo_metadata::{semver::Version, Package};
+use plugin::prelude::*;
+use serde_json::Value;
+
+/// Semver compatibility level of a change, from the lowest.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Bump {
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl Bump {
+    /// Level of a version bump under cargo's rules: the leftmost non-zero
+    /// component is the major one, e.g. 0.1.0 -> 0.2.0 is a major bump.
+    fn new(old: &Version, new: &Version) -> Self {
+        if new <= old {
+            Bump::None
+        } else if new.major != old.major {
+            Bump::Major
+        } else if new.minor != old.minor {
+            if new.major == 0 {
+                Bump::Major
+            } else {
+                Bump::Minor
+            }
+        } else if new.major == 0 && new.minor == 0 {
+            Bump::Major
+        } else if new.major == 0 {
+            Bump::Minor
+        } else {
+            Bump::Patch
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Removed,
+    KindChanged,
+    SignatureChanged,
+    /// a trait item without a default is added to an existing trait
+    RequiredItemAdded,
+}
+
+/// A semver-breaking change in the public API.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct BreakingChange {
+    pub change: ChangeKind,
+    /// e.g. `foo::Bar::new`
+    pub path: String,
+    /// e.g. function, struct, method, variant or trait_item
+    pub item: String,
+}
+
+/// Public API of the checkout compared with the last release.
+#[derive(Debug, Serialize)]
+pub struct SemverReport {
+    pub baseline: String,
+    pub current: String,
+    pub breaking: Vec<BreakingChange>,
+    /// public items not in the last release
+    pub added: usize,
+    /// bump required by the changes
+    pub required: Bump,
+    /// bump in Cargo.toml since the last release
+    pub actual: Bump,
+    pub bump_ok: bool,
+    /// changes not detected, so `bump_ok` may be true for breaking changes
+    pub unchecked: &'static [&'static str],
+}
+
+/// Breaking changes the comparison of public items can't see.
+const UNCHECKED: &[&str] = &[
+    "trait implementations, including auto traits like Send and Sync",
+    "macro signatures",
+    "items re-exported from other crates",
+];
+
+impl SemverReport {
+    /// Compare rustdoc JSON of the lib of the unpacked release with the
+    /// checkout. Returns None if the package has no lib.
+    pub fn new(
+        pkg: &Package,
+        meta: &Metadata,
+        release_dir: &Utf8Path,
+        baseline: &Version,
+    ) -> Result<Option<Self>> {
+        let Some(lib) = pkg.targets.iter().find(|t| t.is_lib()) else {
+            return Ok(None);
+        };
+        let _span = error_span!("semver", pkg = pkg.name.as_str(), %baseline).entered();
+        let lib_name = lib.name.replace('-', "_");
+
+        // the unpacked release isn't in any workspace
+        let manifest = release_dir.join("Cargo.toml");
+        let mut text = std::fs::read_to_string(&manifest)?;
+        if !text.contains("\n[workspace]") {
+            text.push_str("\n[workspace]\n");
+            std::fs::write(&manifest, text)?;
+        }
+        let old = rustdoc_json(
+            release_dir,
+            &manifest,
+            &release_dir.join("target"),
+            &lib_name,
+        )?;
+        let new = rustdoc_json(
+            &meta.workspace_root,
+            &pkg.manifest_path,
+            &meta.target_directory.join("os-checker-semver"),
+            &lib_name,
+        )?;
+        Ok(Some(Self::compare(&old, &new, baseline, &pkg.version)))
+    }
+
+    fn compare(old: &Value, new: &Value, baseline: &Version, current: &Version) -> Self {
+        let old = public_api(old);
+        let new = public_api(new);
+
+        let mut breaking = Vec::new();
+        for (path, old_item) in &old {
+            let change = match new.get(path) {
+                None => ChangeKind::Removed,
+                Some(new_item) if new_item.kind != old_item.kind => ChangeKind::KindChanged,
+                Some(new_item) if new_item.sig != old_item.sig => ChangeKind::SignatureChanged,
+                Some(_) => continue,
+            };
+            breaking.push(BreakingChange {
+                change,
+                path: path.clone(),
+                item: old_item.kind.clone(),
+            });
+        }
+        let mut added = 0;
+        for (path, new_item) in &new {
+            if old.contains_key(path) {
+                continue;
+            }
+            added += 1;
+            let in_old_trait = path
+                .rsplit_once("::")
+                .is_some_and(|(parent, _)| old.get(parent).is_some_and(|p| p.kind == "trait"));
+            if new_item.required && in_old_trait {
+                breaking.push(BreakingChange {
+                    change: ChangeKind::RequiredItemAdded,
+                    path: path.clone(),
+                    item: new_item.kind.clone(),
+                });
+            }
+        }
+
+        let required = if !breaking.is_empty() {
+            Bump::Major
+        } else if added != 0 {
+            Bump::Minor
+        } else {
+            Bump::None
+        };
+        let actual = Bump::new(baseline, current);
+        SemverReport {
+            baseline: baseline.to_string(),
+            current: current.to_string(),
+            breaking,
+            added,
+            required,
+            actual,
+            bump_ok: actual >= required,
+            unchecked: UNCHECKED,
+        }
+    }
+}
+
+/// `cargo rustdoc --lib -- --output-format json`, run in `dir` to respect
+/// its cargo config.
+fn rustdoc_json(
+    dir: &Utf8Path,
+    manifest: &Utf8Path,
+    target_dir: &Utf8Path,
+    lib_name: &str,
+) -> Result<Value> {
+    let output = cmd!(
+        "cargo",
+        "rustdoc",
+        "--lib",
+        "--manifest-path",
+        manifest,
+        "--",
+        "-Zunstable-options",
+        "--output-format=json"
+    )
+    .dir(dir)
+    .env("CARGO_TARGET_DIR", target_dir)
+    .stdout_null()
+    .stderr_capture()
+    .unchecked()
+    .run()?;
+    ensure!(
+        output.status.success(),
+        "Failed to generate rustdoc JSON for {manifest}:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json = target_dir.join("doc").join(format!("{lib_name}.json"));
+    let text = std::fs::read_to_string(&json).with_context(|| format!("Failed to read {json}"))?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+#[derive(Debug)]
+struct ApiItem {
+    kind: String,
+    /// normalized signature including generic bounds; None for macros
+    sig: Option<String>,
+    /// a trait item without a default, which implementors must provide
+    required: bool,
+}
+
+/// Public items of the local crate keyed by path, including struct fields,
+/// enum variants, inherent methods and trait items which are not in `paths`.
+fn public_api(doc: &Value) -> IndexMap<String, ApiItem> {
+    let index = &doc["index"];
+    let get = |id: &Value| match id {
+        Value::String(s) => &index[s],
+        id => &index[id.to_string()],
+    };
+    let is_public = |item: &Value| item["visibility"] == "public";
+    let ids = |value: &Value| value.as_array().cloned().unwrap_or_default();
+
+    let mut api = IndexMap::new();
+    let Some(paths) = doc["paths"].as_object() else {
+        return api;
+    };
+    for (id, summary) in paths {
+        if summary["crate_id"] != 0 {
+            continue;
+        }
+        let Some(path) = summary["path"].as_array() else {
+            continue;
+        };
+        let path: Vec<_> = path.iter().filter_map(|s| s.as_str()).collect();
+        let path = path.join("::");
+        let kind = summary["kind"].as_str().unwrap_or_default().to_owned();
+        let item = &index[id];
+        if item.is_null() {
+            continue;
+        }
+        api.insert(path.clone(), ApiItem::new(&kind, item));
+
+        let inner = &item["inner"][kind.as_str()];
+        let mut members = Vec::new();
+        // public fields of structs; variants are public with enums
+        members.extend(
+            ids(&inner["kind"]["plain"]["fields"])
+                .iter()
+                .map(get)
+                .filter(|field| is_public(field))
+                .map(|field| ("struct_field", field)),
+        );
+        members.extend(ids(&inner["variants"]).iter().map(|v| ("variant", get(v))));
+        // items of traits are public with traits
+        members.extend(ids(&inner["items"]).iter().map(|i| ("trait_item", get(i))));
+        for imp in ids(&inner["impls"]) {
+            let imp = &get(&imp)["inner"]["impl"];
+            if !imp["trait"].is_null() {
+                continue;
+            }
+            members.extend(
+                ids(&imp["items"])
+                    .iter()
+                    .map(get)
+                    .filter(|method| is_public(method))
+                    .map(|method| ("method", method)),
+            );
+        }
+        for (kind, member) in members {
+            if let Some(name) = member["name"].as_str() {
+                api.insert(format!("{path}::{name}"), ApiItem::new(kind, member));
+            }
+        }
+    }
+    api
+}
+
+impl ApiItem {
+    fn new(kind: &str, item: &Value) -> Self {
+        let inner = &item["inner"];
+        let required = kind == "trait_item"
+            && (inner["function"]["has_body"] == false
+                || (inner.get("assoc_const").is_some() && inner["assoc_const"]["value"].is_null())
+                || (inner.get("assoc_type").is_some() && inner["assoc_type"]["type"].is_null()));
+        ApiItem {
+            kind: kind.to_owned(),
+            sig: signature(inner),
+            required,
+        }
+    }
+}
+
+/// The inner item with ids and members stripped, since ids differ between
+/// builds and members are compared on their own. Generics and bounds are
+/// kept, e.g. adding a bound to a type parameter changes the signature.
+fn signature(inner: &Value) -> Option<String> {
+    fn normalize(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for key in [
+                    "id",
+                    "impls",
+                    "items",
+                    "fields",
+                    "variants",
+                    "implementations",
+                ] {
+                    map.remove(key);
+                }
+                // fields of tuple structs and variants are ids, or null if
+                // private
+                if let Some(Value::Array(fields)) = map.get_mut("tuple") {
+                    for field in fields {
+                        *field = Value::Bool(!field.is_null());
+                    }
+                }
+                map.values_mut().for_each(normalize);
+            }
+            Value::Array(v) => v.iter_mut().for_each(normalize),
+            _ => (),
+        }
+    }
+
+    let (kind, value) = inner.as_object()?.iter().next()?;
+    // the body of a macro is not its signature
+    if kind == "macro" || kind == "proc_macro" {
+        return None;
+    }
+    let mut sig = value.clone();
+    normalize(&mut sig);
+    Some(sig.to_string())
+}
+
+#[test]
+fn compare_public_api() {
+    use serde_json::json;
+
+    let func = |input: &str| {
+        json!({ "function": {
+            "sig": { "inputs": [["a", { "primitive": input }]], "output": null },
+            "generics": { "params": [] },
+            "has_body": true
+        }})
+    };
+    let doc = |f_input: &str, with_g: bool| {
+        let mut paths = json!({
+            "1": { "crate_id": 0, "path": ["c", "f"], "kind": "function" },
+            "2": { "crate_id": 0, "path": ["c", "S"], "kind": "struct" },
+            "9": { "crate_id": 1, "path": ["std", "X"], "kind": "struct" }
+        });
+        if with_g {
+            paths["5"] = json!({ "crate_id": 0, "path": ["c", "g"], "kind": "function" });
+        }
+        json!({
+            "paths": paths,
+            "index": {
+                "1": { "name": "f", "visibility": "public", "inner": func(f_input) },
+                "2": { "name": "S", "visibility": "public", "inner": { "struct": {
+                    "kind": { "plain": { "fields": [3] } }, "impls": [4]
+                }}},
+                "3": { "name": "x", "visibility": "public", "inner": { "struct_field": { "primitive": "u8" } } },
+                "4": { "inner": { "impl": { "trait": null, "items": [6] } } },
+                "5": { "name": "g", "visibility": "public", "inner": func("u8") },
+                "6": { "name": "new", "visibility": "public", "inner": func("u8") }
+            }
+        })
+    };
+
+    // an enum, a trait and a const which change in the new version
+    let other_items = |new: bool| {
+        let variants = if new { json!([8]) } else { json!([7, 8]) };
+        let trait_items = if new { json!([10, 11]) } else { json!([10]) };
+        let bound = if new {
+            json!([{ "trait_bound": { "trait": { "path": "Clone", "id": 20 } } }])
+        } else {
+            json!([])
+        };
+        let paths = json!({
+            "12": { "crate_id": 0, "path": ["c", "E"], "kind": "enum" },
+            "13": { "crate_id": 0, "path": ["c", "T"], "kind": "trait" },
+            "14": { "crate_id": 0, "path": ["c", "C"], "kind": "constant" }
+        });
+        let index = json!({
+            "7": { "name": "A", "inner": { "variant": { "kind": "plain" } } },
+            "8": { "name": "B", "inner": { "variant": { "kind": { "tuple": [15] } } } },
+            "10": { "name": "m", "inner": func("u8") },
+            "11": { "name": "n", "inner": { "function": { "has_body": false } } },
+            "12": { "name": "E", "visibility": "public", "inner": { "enum": {
+                "generics": { "params": [{ "name": "T", "kind": { "type": { "bounds": bound } } }] },
+                "variants": variants
+            }}},
+            "13": { "name": "T", "visibility": "public", "inner": { "trait": { "items": trait_items } } },
+            "14": { "name": "C", "visibility": "public", "inner": { "constant": {
+                "type": { "primitive": if new { "u16" } else { "u8" } }
+            }}}
+        });
+        (paths, index)
+    };
+    let with_other_items = |mut doc: Value, new: bool| {
+        let (paths, index) = other_items(new);
+        for (key, value) in paths.as_object().unwrap() {
+            doc["paths"][key] = value.clone();
+        }
+        for (key, value) in index.as_object().unwrap() {
+            doc["index"][key] = value.clone();
+        }
+        doc
+    };
+
+    let old = doc("u32", true);
+    let api = public_api(&old);
+    assert_eq!(
+        api.keys().collect::<Vec<_>>(),
+        ["c::f", "c::S", "c::S::x", "c::S::new", "c::g"]
+    );
+
+    let report = SemverReport::compare(
+        &with_other_items(old.clone(), false),
+        &with_other_items(doc("u32", true), true),
+        &Version::new(1, 2, 0),
+        &Version::new(2, 0, 0),
+    );
+    dbg!(&report);
+    let changes: Vec<_> = report
+        .breaking
+        .iter()
+        .map(|b| (&b.change, b.path.as_str()))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (&ChangeKind::SignatureChanged, "c::E"),
+            (&ChangeKind::Removed, "c::E::A"),
+            (&ChangeKind::SignatureChanged, "c::C"),
+            (&ChangeKind::RequiredItemAdded, "c::T::n"),
+        ]
+    );
+    assert!(report.bump_ok);
+
+    let report = SemverReport::compare(
+        &old,
+        &doc("u64", false),
+        &Version::new(1, 2, 0),
+        &Version::new(1, 3, 0),
+    );
+    dbg!(&report);
+    assert_eq!(report.breaking.len(), 2);
+    assert_eq!(report.breaking[0].change, ChangeKind::SignatureChanged);
+    assert_eq!(report.breaking[1].change, ChangeKind::Removed);
+    assert_eq!(report.required, Bump::Major);
+    assert_eq!(report.actual, Bump::Minor);
+    assert!(!report.bump_ok);
+
+    assert_eq!(
+        Bump::new(&Version::new(0, 1, 2), &Version::new(0, 2, 0)),
+        Bump::Major
+    );
+    assert_eq!(
+        Bump::new(&Version::new(0, 1, 2), &Version::new(0, 1, 3)),
+        Bump::Minor
+    );
+    assert_eq!(
+        Bump::new(&Version::new(1, 1, 2), &Version::new(1, 1, 2)),
+        Bump::None
+    );
+}
@@ -0,0 +1,379 @@
+use cargo_metadata::Package;
+use plugin::prelude::*;
+use syn::{spanned::Spanned, visit::Visit};
+
+/// Size of the source code of a package.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct SourceStats {
+    pub files: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub blanks: usize,
+    /// functions with `#[test]` or `#[xxx::test]`
+    pub test_fns: usize,
+    /// code lines in test targets, `#[cfg(test)]` modules and test functions
+    pub test_code: usize,
+    /// code lines not counted in test_code
+    pub lib_code: usize,
+    /// test_code / lib_code; None if there is no lib code
+    pub test_to_code_ratio: Option<f64>,
+}
+
+impl SourceStats {
+    pub fn new(pkg: &Package) -> Self {
+        // test files, and the `tests` dir if a test target is in it
+        let test_files: Vec<_> = pkg
+            .targets
+            .iter()
+            .filter(|t| t.is_test())
+            .map(|t| t.src_path.as_path())
+            .collect();
+        let tests_dir = pkg.manifest_path.with_file_name("tests");
+        let tests_dir = test_files
+            .iter()
+            .any(|f| f.starts_with(&tests_dir))
+            .then_some(tests_dir);
+
+        let mut stats = SourceStats::default();
+        for path in pkg_source_files(pkg) {
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let is_test_target = test_files.contains(&path.as_path())
+                || tests_dir.as_ref().is_some_and(|dir| path.starts_with(dir));
+            stats.add_file(&text, is_test_target);
+        }
+        stats.lib_code = stats.code - stats.test_code;
+        stats.test_to_code_ratio =
+            (stats.lib_code != 0).then(|| stats.test_code as f64 / stats.lib_code as f64);
+        stats
+    }
+
+    fn add_file(&mut self, text: &str, is_test_target: bool) {
+        let lines = classify_lines(text);
+        self.files += 1;
+        for kind in &lines {
+            match kind {
+                LineKind::Code => self.code += 1,
+                LineKind::Comment => self.comments += 1,
+                LineKind::Blank => self.blanks += 1,
+            }
+        }
+
+        let mut tests = TestVisitor::default();
+        if let Ok(ast) = syn::parse_file(text) {
+            tests.visit_file(&ast);
+        }
+        self.test_fns += tests.test_fns;
+
+        let is_code_in = |(start, end): (usize, usize)| {
+            // span lines start from 1
+            lines[start.saturating_sub(1)..end.min(lines.len())]
+                .iter()
+                .filter(|k| **k == LineKind::Code)
+                .count()
+        };
+        self.test_code += if is_test_target {
+            is_code_in((1, lines.len()))
+        } else {
+            tests.ranges.into_iter().map(is_code_in).sum()
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Code,
+    Comment,
+    Blank,
+}
+
+/// Where a line starts: literals and block comments may span lines.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LexState {
+    Code,
+    /// nesting depth of block comments
+    Block(usize),
+    Str,
+    /// number of `#` in a raw string
+    RawStr(usize),
+}
+
+/// A line-based classification: a line with both code and comments is code.
+/// String, raw string and char literals are skipped, so `"src/**/*.rs"`
+/// doesn't start a block comment.
+fn classify_lines(text: &str) -> Vec<LineKind> {
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut state = LexState::Code;
+    text.lines()
+        .map(|line| {
+            let b = line.as_bytes();
+            let mut has_code = matches!(state, LexState::Str | LexState::RawStr(_));
+            let mut has_comment = matches!(state, LexState::Block(_));
+            let mut i = 0;
+            while i < b.len() {
+                let rest = &b[i..];
+                match state {
+                    LexState::Block(depth) => {
+                        if rest.starts_with(b"/*") {
+                            state = LexState::Block(depth + 1);
+                            i += 2;
+                        } else if rest.starts_with(b"*/") {
+                            state = match depth {
+                                1 => LexState::Code,
+                                _ => LexState::Block(depth - 1),
+                            };
+                            i += 2;
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    LexState::Str => {
+                        match b[i] {
+                            b'\\' => i += 1,
+                            b'"' => state = LexState::Code,
+                            _ => (),
+                        }
+                        i += 1;
+                    }
+                    LexState::RawStr(hashes) => {
+                        let closed = b[i] == b'"'
+                            && rest.len() > hashes
+                            && rest[1..=hashes].iter().all(|c| *c == b'#');
+                        if closed {
+                            state = LexState::Code;
+                            i += hashes;
+                        }
+                        i += 1;
+                    }
+                    LexState::Code if b[i].is_ascii_whitespace() => i += 1,
+                    LexState::Code if rest.starts_with(b"//") => {
+                        has_comment = true;
+                        break;
+                    }
+                    LexState::Code if rest.starts_with(b"/*") => {
+                        has_comment = true;
+                        state = LexState::Block(1);
+                        i += 2;
+                    }
+                    LexState::Code => {
+                        has_code = true;
+                        // a prefix like `r`, `br` or `cr` of a raw string
+                        let raw = match rest {
+                            [b'r', ..] => Some(1),
+                            [b'b' | b'c', b'r', ..] => Some(2),
+                            _ => None,
+                        };
+                        let hashes =
+                            raw.map(|n| rest[n..].iter().take_while(|c| **c == b'#').count());
+                        match (raw, hashes) {
+                            (Some(n), Some(h)) if rest.get(n + h) == Some(&b'"') => {
+                                state = LexState::RawStr(h);
+                                i += n + h + 1;
+                            }
+                            _ if b[i] == b'"' => {
+                                state = LexState::Str;
+                                i += 1;
+                            }
+                            _ if b[i] == b'\'' => i += char_literal_len(&line[i..]),
+                            _ if is_ident(b[i]) => {
+                                // skip the whole identifier to not take its
+                                // tail as a raw string prefix
+                                i += rest.iter().take_while(|c| is_ident(**c)).count();
+                            }
+                            _ => i += 1,
+                        }
+                    }
+                }
+            }
+            if has_code {
+                LineKind::Code
+            } else if has_comment {
+                LineKind::Comment
+            } else {
+                LineKind::Blank
+            }
+        })
+        .collect()
+}
+
+/// Length of a char literal like `'a'` or `'\''` at the start of `text`, or
+/// 1 for the quote of a lifetime like `'a`.
+fn char_literal_len(text: &str) -> usize {
+    let mut chars = text.char_indices().skip(1);
+    match chars.next() {
+        Some((_, '\\')) => {
+            // skip the escaped char, then find the closing quote
+            chars.next();
+            chars
+                .find(|(_, c)| *c == '\'')
+                .map_or(1, |(idx, _)| idx + 1)
+        }
+        Some((_, _)) => match chars.next() {
+            Some((idx, '\'')) => idx + 1,
+            _ => 1,
+        },
+        None => 1,
+    }
+}
+
+/// Find test functions and line ranges of test code.
+#[derive(Default)]
+struct TestVisitor {
+    test_fns: usize,
+    /// `(start_line, end_line)` of `#[cfg(test)]` modules and test functions
+    /// outside of them
+    ranges: Vec<(usize, usize)>,
+    in_test_mod: bool,
+}
+
+fn is_test_attr(attr: &syn::Attribute) -> bool {
+    let path = attr.path();
+    path.segments.last().is_some_and(|seg| seg.ident == "test")
+}
+
+fn is_cfg_test(attr: &syn::Attribute) -> bool {
+    let mut found = false;
+    if attr.path().is_ident("cfg") {
+        let _ = attr.parse_nested_meta(|meta| {
+            found |= meta.path.is_ident("test");
+            Ok(())
+        });
+    }
+    found
+}
+
+fn lines_of(span: proc_macro2::Span) -> (usize, usize) {
+    (span.start().line, span.end().line)
+}
+
+impl<'ast> Visit<'ast> for TestVisitor {
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        if !self.in_test_mod && i.attrs.iter().any(is_cfg_test) {
+            self.ranges.push(lines_of(i.span()));
+            self.in_test_mod = true;
+            syn::visit::visit_item_mod(self, i);
+            self.in_test_mod = false;
+        } else {
+            syn::visit::visit_item_mod(self, i);
+        }
+    }
+
+    fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
+        if i.attrs.iter().any(is_test_attr) {
+            self.test_fns += 1;
+            if !self.in_test_mod {
+                self.ranges.push(lines_of(i.span()));
+            }
+        }
+        syn::visit::visit_item_fn(self, i);
+    }
+}
+
+/// Rust source files of a package, collected from the dirs of its targets'
+/// `src_path`. A target whose `src_path` is directly in the package root,
+/// like `build.rs`, only contributes itself.
+pub fn pkg_source_files(pkg: &Package) -> Vec<Utf8PathBuf> {
+    let Some(pkg_dir) = pkg.manifest_path.parent() else {
+        return Vec::new();
+    };
+
+    let mut files = Vec::new();
+    for target in &pkg.targets {
+        let src = &target.src_path;
+        match src.parent() {
+            Some(dir) if dir != pkg_dir => files.extend(rust_files(dir)),
+            _ => files.push(src.clone()),
+        }
+    }
+    files.sort_unstable();
+    files.dedup();
+    files
+}
+
+/// `.rs` files in the dir, not descending into nested packages.
+fn rust_files(dir: &Utf8Path) -> impl Iterator<Item = Utf8PathBuf> + '_ {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(move |e| {
+            e.path() == dir || !(e.file_type().is_dir() && e.path().join("Cargo.toml").exists())
+        })
+        .filter_map(|entry| {
+            let e = entry.ok()?;
+            let path = Utf8PathBuf::from_path_buf(e.into_path()).ok()?;
+            (path.extension() == Some("rs")).then_some(path)
+        })
+}
+
+#[test]
+fn source_stats() {
+    let code = "//! doc
+use std::fs;
+
+/* block
+   comment */
+fn lib() {} // trailing
+
+#[test]
+fn t() {
+    lib();
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn t2() {}
+}
+";
+    let mut stats = SourceStats::default();
+    stats.add_file(code, false);
+    assert_eq!(stats.files, 1);
+    assert_eq!(stats.comments, 3);
+    assert_eq!(stats.blanks, 3);
+    assert_eq!(stats.code, 11);
+    assert_eq!(stats.test_fns, 2);
+    assert_eq!(stats.test_code, 9);
+
+    use LineKind::*;
+    let code = r##"let g = "src/**/*.rs";
+let c = '"'; let q = '\''; // "
+let r = r#"/* "# ;
+let s = "multi
+/* line";
+fn f<'a>(x: &'a str) {} /* block
+*/
+"##;
+    assert_eq!(
+        classify_lines(code),
+        [Code, Code, Code, Code, Code, Code, Comment]
+    );
+}
+
+#[test]
+fn source_stats_of_fixture() {
+    let meta = super::fixture_metadata();
+    let pkg = meta.root_package().unwrap();
+    let files: Vec<_> = pkg_source_files(pkg)
+        .into_iter()
+        .map(|f| f.strip_prefix(&meta.workspace_root).unwrap().to_owned())
+        .collect();
+    // members in the package dir are not included
+    assert_eq!(
+        files,
+        ["src/lib.rs", "src/main.rs", "src/util.rs", "tests/it.rs"]
+    );
+
+    let stats = SourceStats::new(pkg);
+    let expected = SourceStats {
+        files: 4,
+        code: 21,
+        comments: 3,
+        blanks: 2,
+        test_fns: 2,
+        // the `tests` module in lib.rs and the whole tests/it.rs
+        test_code: 11,
+        lib_code: 10,
+        test_to_code_ratio: Some(11.0 / 10.0),
+    };
+    assert_eq!(stats, expected);
+}
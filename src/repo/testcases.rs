@@ -1,32 +1,73 @@
-use super::miri::{cargo_miri, install_miri};
-use crate::nextest::{run_testcases, Event, Report};
+use super::miri::{install_miri, run_miri, MiriStatus, MiriUnsupported};
+use super::sanitizer::{self, SanitizerReport, SanitizerResult};
+use crate::nextest::{run_testcases, tail, Attempt, Event, Failure, Report};
 use nextest_metadata::{RustTestSuiteSummary, TestListSummary};
-use plugin::prelude::*;
-
-fn test_list(dir: &Utf8Path) -> Result<TestListSummary> {
-    let mut command = nextest_metadata::ListCommand::new();
-    command.current_dir(dir);
-    command
-        .exec()
-        .with_context(|| format!("fail to run `cargo nextest list` in {dir}"))
-}
+use plugin::prelude::{indexmap::IndexSet, *};
 
 pub type PkgTests = IndexMap<String, TestCases>;
 
+/// Tests in a workspace attributed to packages.
+#[derive(Debug, Default)]
+pub struct WorkspaceTests {
+    pub pkgs: PkgTests,
+    /// Some if tests can't be fully attributed to packages.
+    pub warning: Option<TestsWarning>,
+}
+
+/// Disagreement between the test list and test results, or tests that
+/// can't be attributed to a package.
+#[derive(Debug, Serialize)]
+pub struct TestsWarning {
+    pub workspace_root: Utf8PathBuf,
+    pub message: String,
+    /// `pkg::binary$testcase` not attributed to any package in the output
+    pub unattributed: Vec<String>,
+}
+
+impl TestsWarning {
+    pub fn new(workspace_root: &Utf8Path, message: String) -> Self {
+        TestsWarning {
+            workspace_root: workspace_root.to_owned(),
+            message,
+            unattributed: Vec::new(),
+        }
+    }
+}
+
 // FIXME: how should we handle doc tests?
 
 // nextest reports all member tests even if it's run under a member, so we just run under workspace
-pub fn get(workspace_root: &Utf8Path) -> Result<PkgTests> {
+// full output of failed tests are written under log_dir
+// summary is the test list from `build::list_tests`
+pub fn get(
+    workspace_root: &Utf8Path,
+    target_dir: &Utf8Path,
+    log_dir: &Utf8Path,
+    summary: TestListSummary,
+) -> Result<WorkspaceTests> {
     let _span = error_span!("get_and_run", ?workspace_root).entered();
 
     if let Err(err) = install_miri(workspace_root) {
         error!(?err, "Failed to install miri!");
     }
 
-    info!("test_list starts");
-    let summary = test_list(workspace_root).with_context(|| "failed to get test list")?;
     info!("run_testcases starts");
     let report = run_testcases(workspace_root).with_context(|| "failed to run tests")?;
+    let sanitizers: Vec<_> = crate::config::sanitizers()
+        .into_iter()
+        .filter_map(|san| {
+            info!(%san, "sanitizer starts");
+            sanitizer::run(workspace_root, target_dir, san)
+                .inspect_err(|err| error!(?err, %san, "Failed to run tests with sanitizer"))
+                .ok()
+        })
+        .collect();
+    let ctx = RunCtx {
+        workspace_root,
+        log_dir,
+        report: &report,
+        sanitizers: &sanitizers,
+    };
 
     let workspace_tests_count = summary.test_count;
     // nextest will report all bins even if zero testcase, so don't show them
@@ -41,14 +82,17 @@ pub fn get(workspace_root: &Utf8Path) -> Result<PkgTests> {
             continue;
         }
 
-        let test = TestBinary::new(ele, &report, workspace_root);
+        let test = TestBinary::new(ele, &ctx);
         if let Some((_, _, tests)) = map.get_full_mut(&ele.package_name) {
             tests.tests.push(test);
         } else {
             let tests = TestCases {
                 tests: vec![test],
                 failed: 0,
+                flaky: 0,
                 duration_ms: 0,
+                wall_time_ms: 0,
+                nextest_wall_ms: report.wall_ms,
                 pkg_tests_count: 0,
                 workspace_tests_count,
             };
@@ -59,26 +103,90 @@ pub fn get(workspace_root: &Utf8Path) -> Result<PkgTests> {
     for ele in map.values_mut() {
         for t in &ele.tests {
             ele.failed += t.failed;
+            ele.flaky += t.flaky;
             ele.duration_ms += t.duration_ms;
+            ele.wall_time_ms += t.wall_time_ms.unwrap_or(0) as usize;
             ele.pkg_tests_count += t.testcases.len();
         }
     }
 
     let sum_pkg_tests_count: usize = map.values().map(|p| p.pkg_tests_count).sum();
-    ensure!(
-        sum_pkg_tests_count == workspace_tests_count,
+    let warning = reconcile(
+        workspace_root,
+        &summary,
+        &report,
+        sum_pkg_tests_count,
+        workspace_tests_count,
+    );
+
+    Ok(WorkspaceTests { pkgs: map, warning })
+}
+
+/// Check the test counts and find test results that don't belong to any
+/// listed test. Instead of failing, the mismatch is reported as a warning.
+fn reconcile(
+    workspace_root: &Utf8Path,
+    summary: &TestListSummary,
+    report: &Report,
+    sum_pkg_tests_count: usize,
+    workspace_tests_count: usize,
+) -> Option<TestsWarning> {
+    let listed: IndexSet<[&str; 3]> = summary
+        .rust_suites
+        .values()
+        .flat_map(|ele| {
+            let pkg_name = &*ele.package_name;
+            let bin_name = &*ele.binary.binary_name;
+            ele.test_cases
+                .keys()
+                .map(move |name| [pkg_name, bin_name, name.as_str()])
+        })
+        .collect();
+    let unattributed: Vec<_> = report
+        .testcases
+        .keys()
+        .filter(|name| !listed.contains(&name.as_array()))
+        .map(|name| name.to_string())
+        .collect();
+
+    if sum_pkg_tests_count == workspace_tests_count && unattributed.is_empty() {
+        return None;
+    }
+
+    let message = format!(
         "test cases count are not equal: sum_pkg_tests_count ({sum_pkg_tests_count}) \
-         ≠ workspace_tests_count ({workspace_tests_count})"
+         vs workspace_tests_count ({workspace_tests_count}); \
+         {} test results are not in the test list",
+        unattributed.len()
     );
+    warn!(message);
+    Some(TestsWarning {
+        workspace_root: workspace_root.to_owned(),
+        message,
+        unattributed,
+    })
+}
 
-    Ok(map)
+/// Test runs in a workspace shared by all testcases.
+pub struct RunCtx<'a> {
+    pub workspace_root: &'a Utf8Path,
+    /// full output of failed tests are written under log_dir
+    pub log_dir: &'a Utf8Path,
+    pub report: &'a Report,
+    pub sanitizers: &'a [SanitizerReport],
 }
 
 #[derive(Debug, Serialize)]
 pub struct TestCases {
     pub tests: Vec<TestBinary>,
     pub failed: usize,
+    /// how many testcases fail at first but pass on a retry
+    pub flaky: usize,
     pub duration_ms: usize,
+    /// sum of wall-clock time of test binaries in ms
+    pub wall_time_ms: usize,
+    /// wall time of `cargo nextest run` in the whole workspace
+    pub nextest_wall_ms: u64,
     pub pkg_tests_count: usize,
     pub workspace_tests_count: usize,
 }
@@ -93,48 +201,90 @@ pub struct TestBinary {
     pub testcases: Vec<TestCase>,
     /// how many testcases are failed
     pub failed: usize,
+    /// how many testcases are flaky
+    pub flaky: usize,
     /// total duration in ms; maybe zero for various reasons
     pub duration_ms: usize,
+    /// wall-clock time in ms of running the test binary
+    pub wall_time_ms: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct TestCase {
-    name: String,
+    pub name: String,
     status: Option<Event>,
     duration_ms: Option<u32>,
+    /// stdout of the test truncated to the size limit, kept for consumers
+    /// of the old schema; None means no error
     error: Option<String>,
+    failure: Option<Failure>,
+    /// failure of the last failed attempt if a retry passes
+    flaky_failure: Option<Failure>,
+    /// each attempt when nextest retries a failed test
+    attempts: Vec<Attempt>,
+    /// fail at first but pass on a retry
+    pub flaky: bool,
     miri_pass: bool,
     miri_output: Option<String>,
     miri_timeout: bool,
+    miri_status: MiriStatus,
+    /// Some if the test can't run under Miri
+    miri_unsupported: Option<MiriUnsupported>,
+    /// the test is rerun under Miri with isolation disabled
+    miri_isolation_disabled: bool,
+    /// empty if no sanitizer is enabled
+    sanitizers: Vec<SanitizerResult>,
 }
 
 impl TestCase {
-    pub fn new(
-        name: &str,
-        pkg_name: &str,
-        kind: &str,
-        bin_name: &str,
-        report: &Report,
-        workspace_root: &Utf8Path,
-    ) -> Self {
-        let (miri_output, miri_pass, miri_timeout) =
-            cargo_miri(pkg_name, kind, bin_name, name, workspace_root);
-        let (status, duration_ms, error) = report.get_test_case(&[pkg_name, bin_name, name]);
+    pub fn new(name: &str, pkg_name: &str, kind: &str, bin_name: &str, ctx: &RunCtx) -> Self {
+        let miri = run_miri(pkg_name, kind, bin_name, name, ctx.workspace_root);
+        let key = [pkg_name, bin_name, name];
+        let limit = crate::config::output_limit();
+        let record = ctx.report.get_test_case(&key);
+        let failure = record.and_then(|r| {
+            let mut failure = r.failure(limit)?;
+            let file = format!("{name}.log");
+            let log = Utf8PathBuf::from_iter(["logs", pkg_name, bin_name, &file]);
+            let path = ctx.log_dir.join(&log);
+            match super::write_file(&path, &r.full_output()) {
+                Ok(()) => failure.log = Some(log.into_string()),
+                Err(err) => error!(?err, ?path, "Failed to write the test log"),
+            }
+            Some(failure)
+        });
         let name = name.to_owned();
         Self {
             name,
-            status,
-            duration_ms,
-            error,
-            miri_pass,
-            miri_output,
-            miri_timeout,
+            status: record.map(|r| r.event),
+            duration_ms: record.and_then(|r| r.duration_ms()),
+            error: record
+                .and_then(|r| r.stdout.as_deref())
+                .map(|stdout| tail(stdout, limit).0.to_owned()),
+            failure,
+            flaky_failure: record.and_then(|r| r.flaky_failure(limit)),
+            attempts: record.map(|r| r.attempts.clone()).unwrap_or_default(),
+            flaky: record.is_some_and(|r| r.is_flaky()),
+            miri_pass: miri.pass,
+            miri_output: miri.output,
+            miri_timeout: miri.timeout,
+            miri_status: miri.status,
+            miri_unsupported: miri.unsupported,
+            miri_isolation_disabled: miri.isolation_disabled,
+            sanitizers: ctx
+                .sanitizers
+                .iter()
+                .filter_map(|san| {
+                    let record = san.report.get_test_case(&key)?;
+                    Some(SanitizerResult::new(san.sanitizer, record, limit))
+                })
+                .collect(),
         }
     }
 }
 
 impl TestBinary {
-    pub fn new(ele: &RustTestSuiteSummary, report: &Report, workspace_root: &Utf8Path) -> Self {
+    pub fn new(ele: &RustTestSuiteSummary, ctx: &RunCtx) -> Self {
         let binary = &ele.binary;
         let pkg_name = &*ele.package_name;
         let bin_name = &*binary.binary_name;
@@ -142,7 +292,7 @@ impl TestBinary {
         let testcases: Vec<_> = ele
             .test_cases
             .keys()
-            .map(|name| TestCase::new(name, pkg_name, kind, bin_name, report, workspace_root))
+            .map(|name| TestCase::new(name, pkg_name, kind, bin_name, ctx))
             .collect();
         let (failed, duration_ms) = testcases.iter().fold((0, 0), |(s, d), t| {
             let d = d + t.duration_ms.unwrap_or(0) as usize;
@@ -152,6 +302,7 @@ impl TestBinary {
                 (s, d)
             }
         });
+        let flaky = testcases.iter().filter(|t| t.flaky).count();
         TestBinary {
             id: binary.binary_id.to_string(),
             kind: binary.kind.to_string(),
@@ -163,7 +314,9 @@ impl TestBinary {
             //     .to_string(),
             testcases,
             failed,
+            flaky,
             duration_ms,
+            wall_time_ms: ctx.report.suite_time_ms(pkg_name, bin_name),
         }
     }
 }
@@ -172,5 +325,13 @@ impl TestBinary {
 #[ignore = "manually trigger this to avoid recursion"]
 fn test_get_testcases() {
     plugin::logger::init();
-    dbg!(get(".".into()).unwrap());
+    let meta = cargo_metadata::MetadataCommand::new().exec().unwrap();
+    let (_, summary) = super::build::list_tests(".".into(), &meta);
+    let tests = get(
+        ".".into(),
+        "target".into(),
+        "target/logs".into(),
+        summary.unwrap(),
+    );
+    dbg!(tests.unwrap());
 }
@@ -0,0 +1,201 @@
+use super::{deps::dep_packages, source::pkg_source_files};
+use cargo_metadata::Package;
+use plugin::prelude::*;
+use syn::{spanned::Spanned, visit::Visit};
+
+/// Amount of `unsafe` usage in a package.
+#[derive(Debug, Default, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct UnsafeCounts {
+    pub unsafe_blocks: usize,
+    pub unsafe_fns: usize,
+    pub unsafe_impls: usize,
+    pub unsafe_traits: usize,
+    pub extern_blocks: usize,
+    /// all lib and bin roots have `#![forbid(unsafe_code)]`
+    pub forbid_unsafe_code: bool,
+}
+
+impl UnsafeCounts {
+    fn add(&mut self, other: &UnsafeCounts) {
+        self.unsafe_blocks += other.unsafe_blocks;
+        self.unsafe_fns += other.unsafe_fns;
+        self.unsafe_impls += other.unsafe_impls;
+        self.unsafe_traits += other.unsafe_traits;
+        self.extern_blocks += other.extern_blocks;
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsafeKind {
+    Block,
+    Fn,
+    Impl,
+    Trait,
+    ExternBlock,
+}
+
+/// Where an unsafe item is.
+#[derive(Debug, Serialize)]
+pub struct UnsafeItem {
+    pub kind: UnsafeKind,
+    /// relative to the package dir
+    pub file: String,
+    pub line: usize,
+}
+
+/// Unsafe inventory of a package.
+#[derive(Debug, Default, Serialize)]
+pub struct UnsafeScan {
+    pub counts: UnsafeCounts,
+    pub items: Vec<UnsafeItem>,
+    /// files that syn fails to parse
+    pub parse_errors: Vec<String>,
+}
+
+/// Scan source files of a package with syn.
+pub fn scan_pkg(pkg: &Package) -> UnsafeScan {
+    let pkg_dir = pkg.manifest_path.parent().unwrap_or(Utf8Path::new(""));
+    let roots: Vec<_> = pkg
+        .targets
+        .iter()
+        .filter(|t| t.is_lib() || t.is_bin())
+        .map(|t| &t.src_path)
+        .collect();
+
+    let mut scan = UnsafeScan::default();
+    let mut forbid = !roots.is_empty();
+    for path in pkg_source_files(pkg) {
+        let file = path.strip_prefix(pkg_dir).unwrap_or(&path).to_string();
+        let ast = match std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| syn::parse_file(&text).map_err(|err| err.to_string()))
+        {
+            Ok(ast) => ast,
+            Err(err) => {
+                scan.parse_errors.push(format!("{file}: {err}"));
+                continue;
+            }
+        };
+        if roots.contains(&&path) {
+            forbid &= forbids_unsafe_code(&ast.attrs);
+        }
+        let mut visitor = Visitor {
+            file: &file,
+            scan: &mut scan,
+        };
+        visitor.visit_file(&ast);
+    }
+    scan.counts.forbid_unsafe_code = forbid;
+    scan
+}
+
+/// Scan transitive normal and build dependencies of a package, like
+/// cargo-geiger. Returns the sum and counts of each dependency.
+pub fn scan_deps(pkg: &Package, meta: &Metadata) -> (UnsafeCounts, IndexMap<String, UnsafeCounts>) {
+    let mut total = UnsafeCounts::default();
+    let mut each = IndexMap::new();
+    for dep in dep_packages(pkg, meta) {
+        let counts = scan_pkg(dep).counts;
+        total.add(&counts);
+        each.insert(format!("{}@{}", dep.name, dep.version), counts);
+    }
+    (total, each)
+}
+
+fn forbids_unsafe_code(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let mut found = false;
+        if matches!(attr.style, syn::AttrStyle::Inner(_)) && attr.path().is_ident("forbid") {
+            let _ = attr.parse_nested_meta(|meta| {
+                found |= meta.path.is_ident("unsafe_code");
+                Ok(())
+            });
+        }
+        found
+    })
+}
+
+struct Visitor<'a> {
+    file: &'a str,
+    scan: &'a mut UnsafeScan,
+}
+
+impl Visitor<'_> {
+    fn push(&mut self, kind: UnsafeKind, span: proc_macro2::Span) {
+        let counts = &mut self.scan.counts;
+        match kind {
+            UnsafeKind::Block => counts.unsafe_blocks += 1,
+            UnsafeKind::Fn => counts.unsafe_fns += 1,
+            UnsafeKind::Impl => counts.unsafe_impls += 1,
+            UnsafeKind::Trait => counts.unsafe_traits += 1,
+            UnsafeKind::ExternBlock => counts.extern_blocks += 1,
+        }
+        self.scan.items.push(UnsafeItem {
+            kind,
+            file: self.file.to_owned(),
+            line: span.start().line,
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for Visitor<'_> {
+    fn visit_expr_unsafe(&mut self, i: &'ast syn::ExprUnsafe) {
+        self.push(UnsafeKind::Block, i.unsafe_token.span());
+        syn::visit::visit_expr_unsafe(self, i);
+    }
+
+    fn visit_signature(&mut self, i: &'ast syn::Signature) {
+        // covers free fns, methods in impls and traits
+        if let Some(token) = &i.unsafety {
+            self.push(UnsafeKind::Fn, token.span());
+        }
+        syn::visit::visit_signature(self, i);
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        if let Some(token) = &i.unsafety {
+            self.push(UnsafeKind::Impl, token.span());
+        }
+        syn::visit::visit_item_impl(self, i);
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        if let Some(token) = &i.unsafety {
+            self.push(UnsafeKind::Trait, token.span());
+        }
+        syn::visit::visit_item_trait(self, i);
+    }
+
+    fn visit_item_foreign_mod(&mut self, i: &'ast syn::ItemForeignMod) {
+        self.push(UnsafeKind::ExternBlock, i.abi.span());
+        syn::visit::visit_item_foreign_mod(self, i);
+    }
+}
+
+#[test]
+fn count_unsafe() {
+    let code = r#"
+#![forbid(unsafe_code)]
+unsafe fn f() { unsafe { g() } }
+unsafe trait T { unsafe fn m(&self); }
+unsafe impl T for () { unsafe fn m(&self) {} }
+extern "C" { fn g(); }
+fn safe() { let _ = unsafe { 1 }; }
+"#;
+    let ast = syn::parse_file(code).unwrap();
+    let mut scan = UnsafeScan::default();
+    Visitor {
+        file: "lib.rs",
+        scan: &mut scan,
+    }
+    .visit_file(&ast);
+    let counts = scan.counts;
+    assert_eq!(counts.unsafe_blocks, 2);
+    assert_eq!(counts.unsafe_fns, 3);
+    assert_eq!(counts.unsafe_traits, 1);
+    assert_eq!(counts.unsafe_impls, 1);
+    assert_eq!(counts.extern_blocks, 1);
+    assert_eq!(scan.items[0].line, 3);
+    assert!(forbids_unsafe_code(&ast.attrs));
+}
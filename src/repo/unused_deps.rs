@@ -0,0 +1,194 @@
+use cargo_metadata::{DependencyKind, Message, Package, Target};
+use plugin::prelude::*;
+
+/// Dependencies reported by the `unused_crate_dependencies` lint in every
+/// target they are available to.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct UnusedDeps {
+    pub normal: Vec<String>,
+    pub dev: Vec<String>,
+    pub build: Vec<String>,
+}
+
+/// How many times a target is compiled, and how many times each extern
+/// crate is reported unused in it.
+#[derive(Debug, Default)]
+struct TargetLints {
+    units: usize,
+    test_units: usize,
+    unused: IndexMap<String, usize>,
+}
+
+impl UnusedDeps {
+    /// Check all targets of the package with `-W unused-crate-dependencies`.
+    /// A separate target dir is used to not invalidate the normal build.
+    ///
+    /// The lint is appended by a rustc wrapper of workspace members, so
+    /// rustflags from the env and the repo's cargo config still apply.
+    pub fn new(pkg: &Package, meta: &Metadata) -> Result<Self> {
+        let _span = error_span!("unused_deps", pkg = pkg.name.as_str()).entered();
+
+        let target_dir = meta.target_directory.join("os-checker-unused-deps");
+        let wrapper = write_wrapper(&target_dir)?;
+        let output = cmd!(
+            "cargo",
+            "check",
+            "--all-targets",
+            "--message-format=json",
+            "--manifest-path",
+            &pkg.manifest_path
+        )
+        .dir(&meta.workspace_root)
+        .env("RUSTC_WORKSPACE_WRAPPER", wrapper)
+        .env("CARGO_TARGET_DIR", target_dir)
+        .stdout_capture()
+        .stderr_null()
+        .unchecked()
+        .run()?;
+        ensure!(
+            output.status.success(),
+            "Failed to check {}: the unused deps are unknown.",
+            pkg.name
+        );
+        let lints = parse_lints(&output.stdout, pkg);
+        Ok(Self::from_lints(pkg, meta, &lints))
+    }
+
+    fn from_lints(pkg: &Package, meta: &Metadata, lints: &IndexMap<String, TargetLints>) -> Self {
+        let mut unused = UnusedDeps::default();
+        let Some(node) = meta
+            .resolve
+            .as_ref()
+            .and_then(|r| r.nodes.iter().find(|n| n.id == pkg.id))
+        else {
+            return unused;
+        };
+
+        for dep in &node.deps {
+            let Some(dep_pkg) = meta.packages.iter().find(|p| p.id == dep.pkg) else {
+                continue;
+            };
+            // a kind may appear for several platforms
+            let mut kinds = Vec::new();
+            for k in &dep.dep_kinds {
+                if !kinds.contains(&k.kind) {
+                    kinds.push(k.kind);
+                }
+            }
+            for kind in kinds {
+                // targets the dep is passed to, and how many of their units
+                // are expected to report it
+                let units = |target: &Target, lints: &TargetLints| match kind {
+                    DependencyKind::Build => target.is_custom_build().then_some(lints.units),
+                    _ if target.is_custom_build() => None,
+                    DependencyKind::Development if target.is_lib() || target.is_bin() => {
+                        Some(lints.test_units)
+                    }
+                    _ => Some(lints.units),
+                };
+                let mut available = pkg
+                    .targets
+                    .iter()
+                    .filter_map(|t| {
+                        let lints = lints.get(&target_key(t))?;
+                        Some((units(t, lints)?, lints))
+                    })
+                    .filter(|(units, _)| *units != 0)
+                    .peekable();
+                if available.peek().is_none() {
+                    continue;
+                }
+                let is_unused = available
+                    .all(|(units, lints)| lints.unused.get(&dep.name).copied() == Some(units));
+                if is_unused {
+                    let list = match kind {
+                        DependencyKind::Development => &mut unused.dev,
+                        DependencyKind::Build => &mut unused.build,
+                        _ => &mut unused.normal,
+                    };
+                    list.push(dep_pkg.name.clone());
+                }
+            }
+        }
+        unused
+    }
+}
+
+/// A script calling rustc with the lint appended.
+fn write_wrapper(target_dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::create_dir_all(target_dir)?;
+    let path = target_dir.join("rustc-wrapper.sh");
+    std::fs::write(
+        &path,
+        "#!/bin/sh\nexec \"$@\" -W unused-crate-dependencies\n",
+    )?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(path)
+}
+
+fn target_key(target: &Target) -> String {
+    format!("{} {}", target.kind.join(","), target.name)
+}
+
+/// `extern crate `foo` is unused in crate `bar``
+fn parse_lints(stdout: &[u8], pkg: &Package) -> IndexMap<String, TargetLints> {
+    let mut map = IndexMap::<String, TargetLints>::new();
+    for message in Message::parse_stream(stdout).flatten() {
+        match message {
+            Message::CompilerArtifact(artifact) if artifact.package_id == pkg.id => {
+                let lints = map.entry(target_key(&artifact.target)).or_default();
+                lints.units += 1;
+                lints.test_units += artifact.profile.test as usize;
+            }
+            Message::CompilerMessage(msg) if msg.package_id == pkg.id => {
+                let diag = &msg.message;
+                if diag.code.as_ref().map(|c| c.code.as_str()) != Some("unused_crate_dependencies")
+                {
+                    continue;
+                }
+                let Some(name) = diag.message.split('`').nth(1) else {
+                    continue;
+                };
+                let lints = map.entry(target_key(&msg.target)).or_default();
+                *lints.unused.entry(name.to_owned()).or_default() += 1;
+            }
+            _ => (),
+        }
+    }
+    map
+}
+
+#[test]
+fn unused_deps_of_fixture() {
+    let meta = super::fixture_metadata();
+    let pkg = meta.root_package().unwrap();
+    let target = |f: fn(&Target) -> bool| target_key(pkg.targets.iter().find(|t| f(t)).unwrap());
+    let lints = |units, unused: &[(&str, usize)]| TargetLints {
+        units,
+        test_units: 1,
+        unused: unused.iter().map(|(k, n)| (k.to_string(), *n)).collect(),
+    };
+
+    // corelib is unused in every unit; helper is used by the bin in test
+    // mode
+    let mut all = IndexMap::new();
+    all.insert(
+        target(Target::is_lib),
+        lints(2, &[("corelib", 2), ("helper", 1)]),
+    );
+    all.insert(target(Target::is_test), lints(1, &[("corelib", 1)]));
+    all.insert(target(Target::is_bin), lints(2, &[("corelib", 2)]));
+    let unused = UnusedDeps::from_lints(pkg, &meta, &all);
+    assert_eq!(unused.normal, ["corelib"]);
+    assert!(unused.dev.is_empty() && unused.build.is_empty());
+
+    // used by one unit of the lib
+    all.insert(
+        target(Target::is_lib),
+        lints(2, &[("corelib", 1), ("helper", 1)]),
+    );
+    let unused = UnusedDeps::from_lints(pkg, &meta, &all);
+    assert!(unused.normal.is_empty());
+}
@@ -0,0 +1 @@
+pub use corelib::corelib as helper;
@@ -0,0 +1,14 @@
+//! A fixture package.
+mod util;
+
+pub fn app() -> u32 {
+    util::one()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn app() {
+        assert_eq!(super::app(), 1);
+    }
+}
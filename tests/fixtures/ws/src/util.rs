@@ -0,0 +1,5 @@
+/* the only
+   util */
+pub fn one() -> u32 {
+    1
+}
@@ -0,0 +1,4 @@
+#[test]
+fn it() {
+    assert_eq!(app::app(), 1);
+}